@@ -0,0 +1,443 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use clap::ValueEnum;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage, GenericImageView, ImageFormat};
+use matrix_sdk::attachment::{
+    AttachmentInfo, BaseAudioInfo, BaseFileInfo, BaseImageInfo, BaseThumbnailInfo, BaseVideoInfo,
+    Thumbnail,
+};
+use matrix_sdk::ruma::events::room::message::{
+    UnstableAmplitude, UnstableAudioDetailsContentBlock,
+};
+use matrix_sdk::ruma::UInt;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tracing::warn;
+
+use crate::terminal;
+
+const THUMBNAIL_SIZE: (u32, u32) = (800, 600);
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+/// Number of amplitudes in the waveform of voice messages.
+const WAVEFORM_LEN: usize = 100;
+/// Upper bound of waveform amplitudes as defined by MSC3246.
+const WAVEFORM_MAX: f32 = 1024.0;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum RecompressFormat {
+    /// Lossy JPEG
+    Jpeg,
+    /// Lossless WebP
+    Webp,
+}
+
+#[derive(Debug)]
+pub(crate) struct ImageOptions {
+    /// Downscale images so that neither side exceeds this size.
+    pub(crate) max_dimension: Option<u32>,
+    pub(crate) format: Option<RecompressFormat>,
+    /// JPEG quality from 1 to 100.
+    pub(crate) quality: u8,
+}
+
+#[derive(Debug)]
+pub(crate) struct AttachmentOptions {
+    /// Overrides the guessed mimetype.
+    pub(crate) mime: Option<mime::Mime>,
+    pub(crate) image: ImageOptions,
+    /// Send the unmodified image as an additional file.
+    pub(crate) keep_original: bool,
+    /// Flag audio attachments as voice messages.
+    pub(crate) voice: bool,
+}
+
+/// A file to be sent, read either from disk or from stdin.
+#[derive(Debug)]
+pub(crate) struct Attachment {
+    pub(crate) file_name: String,
+    pub(crate) data: Vec<u8>,
+}
+
+impl Attachment {
+    /// Loads the attachment; the path "-" reads stdin. Without a
+    /// `file_name`, stdin attachments are named after their mimetype.
+    pub(crate) fn load(
+        path: impl AsRef<Path>,
+        file_name: Option<&str>,
+        content_type: Option<&mime::Mime>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.as_os_str() != "-" {
+            let Some(file_name) = path.file_name().and_then(|s| s.to_str()) else {
+                bail!("invalid file: {:?}", path);
+            };
+            return Ok(Self {
+                file_name: file_name.to_owned(),
+                data: fs::read(path)?,
+            });
+        }
+
+        let data = terminal::read_stdin_to_end()?;
+        let file_name = match file_name {
+            Some(file_name) => file_name.to_owned(),
+            None => {
+                let content_type = match content_type {
+                    Some(content_type) => content_type.clone(),
+                    None => crate::mime::guess_mime_data("", &data),
+                };
+                match mime_guess::get_mime_extensions(&content_type).and_then(|e| e.first()) {
+                    Some(ext) => format!("attachment.{}", ext),
+                    None => "attachment".to_owned(),
+                }
+            }
+        };
+
+        Ok(Self { file_name, data })
+    }
+}
+
+/// Text which is sent along with an attachment.
+#[derive(Debug)]
+pub(crate) struct Caption {
+    pub(crate) body: String,
+    pub(crate) markdown: bool,
+}
+
+fn uint(n: impl Into<u64>) -> Option<UInt> {
+    UInt::new(n.into())
+}
+
+fn blurhash(image: &DynamicImage) -> anyhow::Result<String> {
+    // The blurhash only encodes a few components; a small image is enough.
+    let small = image.thumbnail(32, 32).to_rgba8();
+    blurhash::encode(
+        BLURHASH_COMPONENTS.0,
+        BLURHASH_COMPONENTS.1,
+        small.width(),
+        small.height(),
+        small.as_raw(),
+    )
+    .map_err(|e| anyhow!("blurhash: {:?}", e))
+}
+
+/// Encodes an image as PNG if it has transparency, otherwise as JPEG.
+fn encode_image(image: &DynamicImage) -> anyhow::Result<(Vec<u8>, mime::Mime)> {
+    let mut data = Vec::new();
+    if image.color().has_alpha() {
+        image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+        Ok((data, mime::IMAGE_PNG))
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)?;
+        Ok((data, mime::IMAGE_JPEG))
+    }
+}
+
+fn encode_image_as(
+    image: &DynamicImage,
+    format: ImageFormat,
+    quality: u8,
+) -> anyhow::Result<(Vec<u8>, mime::Mime)> {
+    let mut data = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            JpegEncoder::new_with_quality(&mut data, quality).encode_image(&image.to_rgb8())?;
+            Ok((data, mime::IMAGE_JPEG))
+        }
+        ImageFormat::WebP => {
+            let rgba = image.to_rgba8();
+            // Lossy WebP requires the libwebp C library; lossless is
+            // pure Rust and well suited for e.g. graphs and screenshots.
+            WebPEncoder::new_lossless(&mut data).encode(
+                rgba.as_raw(),
+                rgba.width(),
+                rgba.height(),
+                ColorType::Rgba8,
+            )?;
+            Ok((data, "image/webp".parse().unwrap()))
+        }
+        ImageFormat::Png => {
+            image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+            Ok((data, mime::IMAGE_PNG))
+        }
+        _ => encode_image(image),
+    }
+}
+
+/// Downscales and recompresses an image according to `options`.
+/// Returns `None` if the attachment is left unchanged.
+pub(crate) fn recompress_image(
+    content_type: &mime::Mime,
+    data: &[u8],
+    options: &ImageOptions,
+) -> anyhow::Result<Option<(Vec<u8>, mime::Mime)>> {
+    if content_type.type_() != mime::IMAGE
+        || (options.max_dimension.is_none() && options.format.is_none())
+    {
+        return Ok(None);
+    }
+    // Re-encoding would drop all but the first frame of animations.
+    if content_type.subtype() == mime::GIF {
+        warn!("not recompressing GIF image");
+        return Ok(None);
+    }
+
+    let mut image = image::load_from_memory(data)?;
+    let mut resized = false;
+    if let Some(max) = options.max_dimension {
+        let (width, height) = image.dimensions();
+        if width > max || height > max {
+            image = image.resize(max, max, FilterType::Lanczos3);
+            resized = true;
+        }
+    }
+    // Re-encoding an unchanged image only loses quality or grows the file.
+    if !resized && options.format.is_none() {
+        return Ok(None);
+    }
+
+    let format = match options.format {
+        Some(RecompressFormat::Jpeg) => ImageFormat::Jpeg,
+        Some(RecompressFormat::Webp) => ImageFormat::WebP,
+        None => ImageFormat::from_mime_type(content_type).unwrap_or(ImageFormat::Png),
+    };
+
+    Ok(Some(encode_image_as(&image, format, options.quality)?))
+}
+
+fn image_thumbnail(image: &DynamicImage) -> anyhow::Result<Option<Thumbnail>> {
+    let (width, height) = image.dimensions();
+    if width <= THUMBNAIL_SIZE.0 && height <= THUMBNAIL_SIZE.1 {
+        return Ok(None);
+    }
+
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE.0, THUMBNAIL_SIZE.1);
+    let (data, content_type) = encode_image(&thumbnail)?;
+
+    Ok(Some(Thumbnail {
+        info: Some(BaseThumbnailInfo {
+            width: uint(thumbnail.width()),
+            height: uint(thumbnail.height()),
+            size: uint(data.len() as u64),
+        }),
+        data,
+        content_type,
+    }))
+}
+
+fn image_info(data: &[u8]) -> anyhow::Result<(AttachmentInfo, Option<Thumbnail>)> {
+    let image = image::load_from_memory(data)?;
+    let (width, height) = image.dimensions();
+
+    let info = AttachmentInfo::Image(BaseImageInfo {
+        width: uint(width),
+        height: uint(height),
+        size: uint(data.len() as u64),
+        blurhash: Some(blurhash(&image)?),
+    });
+
+    Ok((info, image_thumbnail(&image)?))
+}
+
+fn probe_audio(content_type: &mime::Mime, data: &[u8]) -> anyhow::Result<Box<dyn FormatReader>> {
+    let mss = MediaSourceStream::new(Box::new(Cursor::new(data.to_vec())), Default::default());
+    let mut hint = Hint::new();
+    hint.mime_type(content_type.essence_str());
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    Ok(probed.format)
+}
+
+/// Reads the duration of the default track with symphonia.
+pub(crate) fn audio_duration(content_type: &mime::Mime, data: &[u8]) -> anyhow::Result<Duration> {
+    let format = probe_audio(content_type, data)?;
+    let Some(track) = format.default_track() else {
+        bail!("no audio track");
+    };
+
+    let params = &track.codec_params;
+    let Some(n_frames) = params.n_frames else {
+        bail!("unknown number of frames");
+    };
+
+    if let Some(time_base) = params.time_base {
+        let time = time_base.calc_time(n_frames);
+        return Ok(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac));
+    }
+    if let Some(sample_rate) = params.sample_rate {
+        return Ok(Duration::from_secs_f64(
+            n_frames as f64 / sample_rate as f64,
+        ));
+    }
+
+    bail!("unknown sample rate")
+}
+
+/// Decodes the default track and computes the peak amplitudes of
+/// `WAVEFORM_LEN` equally sized chunks, scaled to 0..=1024.
+fn audio_waveform(content_type: &mime::Mime, data: &[u8]) -> anyhow::Result<Vec<u16>> {
+    let mut format = probe_audio(content_type, data)?;
+    let Some(track) = format.default_track() else {
+        bail!("no audio track");
+    };
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    // Channels are mixed down to a single one.
+    let mut samples: Vec<f32> = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(e)) => {
+                warn!("skipping undecodable packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buf.copy_interleaved_ref(decoded);
+        samples.extend(
+            buf.samples()
+                .chunks(channels)
+                .map(|frame| frame.iter().map(|s| s.abs()).sum::<f32>() / channels as f32),
+        );
+    }
+
+    if samples.is_empty() {
+        bail!("no audio samples");
+    }
+
+    let chunk_len = samples.len().div_ceil(WAVEFORM_LEN);
+    let peaks: Vec<f32> = samples
+        .chunks(chunk_len)
+        .map(|chunk| chunk.iter().copied().fold(0.0, f32::max))
+        .collect();
+    let max = peaks.iter().copied().fold(0.0, f32::max);
+    if max == 0.0 {
+        return Ok(vec![0; peaks.len()]);
+    }
+
+    Ok(peaks
+        .into_iter()
+        .map(|peak| (peak / max * WAVEFORM_MAX).round() as u16)
+        .collect())
+}
+
+/// Builds the duration and waveform of a voice message. Codecs which
+/// symphonia cannot decode, e.g. opus, are sent without a waveform.
+pub(crate) fn voice_details(
+    content_type: &mime::Mime,
+    data: &[u8],
+) -> anyhow::Result<UnstableAudioDetailsContentBlock> {
+    if content_type.type_() != mime::AUDIO {
+        bail!("voice messages must be audio, got {}", content_type);
+    }
+
+    let duration = audio_duration(content_type, data)
+        .map_err(|e| anyhow!("reading duration of voice message failed: {}", e))?;
+    let waveform = audio_waveform(content_type, data).unwrap_or_else(|e| {
+        warn!("computing waveform failed: {}", e);
+        Vec::new()
+    });
+
+    Ok(UnstableAudioDetailsContentBlock::new(
+        duration,
+        waveform.into_iter().map(UnstableAmplitude::new).collect(),
+    ))
+}
+
+fn audio_info(content_type: &mime::Mime, data: &[u8]) -> anyhow::Result<AttachmentInfo> {
+    Ok(AttachmentInfo::Audio(BaseAudioInfo {
+        duration: Some(audio_duration(content_type, data)?),
+        size: uint(data.len() as u64),
+    }))
+}
+
+fn video_info(data: &[u8]) -> anyhow::Result<AttachmentInfo> {
+    let reader = mp4::Mp4Reader::read_header(Cursor::new(data), data.len() as u64)?;
+    let track = reader
+        .tracks()
+        .values()
+        .find(|t| matches!(t.track_type(), Ok(mp4::TrackType::Video)));
+
+    Ok(AttachmentInfo::Video(BaseVideoInfo {
+        duration: Some(reader.duration()),
+        width: track.and_then(|t| uint(t.width())),
+        height: track.and_then(|t| uint(t.height())),
+        size: uint(data.len() as u64),
+        blurhash: None,
+    }))
+}
+
+fn basic_info(content_type: &mime::Mime, size: Option<UInt>) -> AttachmentInfo {
+    match content_type.type_() {
+        mime::IMAGE => AttachmentInfo::Image(BaseImageInfo {
+            width: None,
+            height: None,
+            size,
+            blurhash: None,
+        }),
+        mime::AUDIO => AttachmentInfo::Audio(BaseAudioInfo {
+            duration: None,
+            size,
+        }),
+        mime::VIDEO => AttachmentInfo::Video(BaseVideoInfo {
+            duration: None,
+            width: None,
+            height: None,
+            size,
+            blurhash: None,
+        }),
+        _ => AttachmentInfo::File(BaseFileInfo { size }),
+    }
+}
+
+/// Collects the metadata, which matrix clients need for rendering
+/// previews or inline players, and generates a thumbnail for images.
+pub(crate) fn attachment_metadata(
+    content_type: &mime::Mime,
+    data: &[u8],
+) -> (AttachmentInfo, Option<Thumbnail>) {
+    let res = match content_type.type_() {
+        mime::IMAGE => image_info(data),
+        mime::AUDIO => audio_info(content_type, data).map(|info| (info, None)),
+        mime::VIDEO => video_info(data).map(|info| (info, None)),
+        _ => return (basic_info(content_type, uint(data.len() as u64)), None),
+    };
+
+    res.unwrap_or_else(|e| {
+        warn!("extracting metadata of {} failed: {}", content_type, e);
+        (basic_info(content_type, uint(data.len() as u64)), None)
+    })
+}
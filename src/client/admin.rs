@@ -0,0 +1,245 @@
+use anyhow::{anyhow, bail};
+use clap::ValueEnum;
+use matrix_sdk::ruma::api::client::membership::{
+    forget_room, invite_user, join_room_by_id_or_alias, leave_room,
+};
+use matrix_sdk::ruma::api::client::room::create_room;
+use matrix_sdk::ruma::api::client::room::Visibility;
+use matrix_sdk::ruma::events::room::encryption::RoomEncryptionEventContent;
+use matrix_sdk::ruma::events::room::member::MembershipState;
+use matrix_sdk::ruma::events::space::child::SpaceChildEventContent;
+use matrix_sdk::ruma::events::space::parent::SpaceParentEventContent;
+use matrix_sdk::ruma::events::InitialStateEvent;
+use matrix_sdk::ruma::{OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomAliasId, RoomId, UserId};
+use matrix_sdk::RoomState;
+use tracing::warn;
+
+use super::resolve::RoomRef;
+use crate::outputs;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum RoomPreset {
+    /// Invite only; guests may join
+    Private,
+    /// Invite only; all invitees get the same power level as the creator
+    #[value(name = "trusted_private")]
+    TrustedPrivate,
+    /// Everyone may join
+    Public,
+}
+
+impl From<RoomPreset> for create_room::v3::RoomPreset {
+    fn from(value: RoomPreset) -> Self {
+        match value {
+            RoomPreset::Private => Self::PrivateChat,
+            RoomPreset::TrustedPrivate => Self::TrustedPrivateChat,
+            RoomPreset::Public => Self::PublicChat,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CreateRoomOptions {
+    pub(crate) name: Option<String>,
+    pub(crate) topic: Option<String>,
+    /// Either the localpart or the full alias on the own homeserver.
+    pub(crate) alias: Option<String>,
+    pub(crate) invite: Vec<OwnedUserId>,
+    pub(crate) preset: Option<RoomPreset>,
+    pub(crate) encrypted: bool,
+    pub(crate) direct: bool,
+    pub(crate) space_parent: Option<OwnedRoomId>,
+}
+
+impl super::Client {
+    fn membership(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        membership: MembershipState,
+    ) -> outputs::Membership {
+        outputs::Membership {
+            room_id: room_id.to_owned(),
+            user_id: user_id.to_owned(),
+            membership,
+        }
+    }
+
+    pub(crate) async fn create_room(
+        &self,
+        options: CreateRoomOptions,
+    ) -> anyhow::Result<outputs::CreatedRoom> {
+        let server_name = self.user_id.server_name().to_owned();
+
+        let alias_name = match options.alias.as_deref() {
+            Some(alias) if alias.starts_with('#') => {
+                let alias = RoomAliasId::parse(alias)?;
+                if alias.server_name() != server_name {
+                    bail!("aliases can only be created on {}", server_name);
+                }
+                Some(alias.alias().to_owned())
+            }
+            Some(alias) => Some(alias.to_owned()),
+            None => None,
+        };
+
+        let mut initial_state = Vec::new();
+        if options.encrypted {
+            let content = RoomEncryptionEventContent::with_recommended_defaults();
+            initial_state.push(InitialStateEvent::new(content).to_raw_any());
+        }
+        if let Some(parent) = &options.space_parent {
+            let mut content = SpaceParentEventContent::new(vec![server_name.clone()]);
+            content.canonical = true;
+            let event = InitialStateEvent {
+                content,
+                state_key: parent.clone(),
+            };
+            initial_state.push(event.to_raw_any());
+        }
+
+        let mut request = create_room::v3::Request::new();
+        request.name = options.name;
+        request.topic = options.topic;
+        request.room_alias_name = alias_name.clone();
+        request.invite = options.invite;
+        request.preset = options.preset.map(Into::into);
+        request.is_direct = options.direct;
+        request.initial_state = initial_state;
+        if matches!(options.preset, Some(RoomPreset::Public)) {
+            request.visibility = Visibility::Public;
+        }
+
+        let room = self.inner.create_room(request).await?;
+        let room_id = room.room_id().to_owned();
+
+        // The child event makes the room show up in the space.
+        if let Some(parent) = &options.space_parent {
+            let content = SpaceChildEventContent::new(vec![server_name.clone()]);
+            let res = match self.inner.get_room(parent) {
+                Some(space) => space
+                    .send_state_event_for_key(&room_id, content)
+                    .await
+                    .map(|_| ())
+                    .map_err(Into::into),
+                None => Err(anyhow!("not a member of {}", parent)),
+            };
+            if let Err(e) = res {
+                warn!("could not add {} to space {}: {}", room_id, parent, e);
+            }
+        }
+
+        let alias = match alias_name {
+            Some(name) => Some(OwnedRoomAliasId::try_from(format!(
+                "#{}:{}",
+                name, server_name
+            ))?),
+            None => None,
+        };
+        let matrix_to_uri = match &alias {
+            Some(alias) => alias.matrix_to_uri(),
+            None => room_id.matrix_to_uri_via([server_name]),
+        };
+
+        Ok(outputs::CreatedRoom {
+            room_id,
+            alias,
+            matrix_to_uri: matrix_to_uri.to_string(),
+        })
+    }
+
+    pub(crate) async fn join_room(
+        &self,
+        room: &RoomRef,
+        reason: Option<String>,
+    ) -> anyhow::Result<outputs::Membership> {
+        let (room_id_or_alias, via) = match room.to_room_or_alias_id() {
+            Some(target) => target,
+            None => (self.resolve_room(room).await?.into(), Vec::new()),
+        };
+        let mut request = join_room_by_id_or_alias::v3::Request::new(room_id_or_alias);
+        request.server_name = via;
+        request.reason = reason;
+
+        let resp = self.inner.send(request, None).await?;
+        Ok(self.membership(&resp.room_id, &self.user_id, MembershipState::Join))
+    }
+
+    pub(crate) async fn leave_room(
+        &self,
+        room_id: &RoomId,
+        reason: Option<String>,
+    ) -> anyhow::Result<outputs::Membership> {
+        let mut request = leave_room::v3::Request::new(room_id.to_owned());
+        request.reason = reason;
+
+        self.inner.send(request, None).await?;
+        Ok(self.membership(room_id, &self.user_id, MembershipState::Leave))
+    }
+
+    /// Forgets a room, which must have been left before.
+    pub(crate) async fn forget_room(
+        &self,
+        room_id: &RoomId,
+    ) -> anyhow::Result<outputs::Membership> {
+        // The store only knows the room as left after a sync.
+        match self.inner.get_room(room_id) {
+            Some(room) if room.state() == RoomState::Left => room.forget().await?,
+            _ => {
+                let request = forget_room::v3::Request::new(room_id.to_owned());
+                self.inner.send(request, None).await?;
+            }
+        }
+        Ok(self.membership(room_id, &self.user_id, MembershipState::Leave))
+    }
+
+    pub(crate) async fn invite_user(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        reason: Option<String>,
+    ) -> anyhow::Result<outputs::Membership> {
+        let room = self.get_joined_room(room_id)?;
+        let recipient = invite_user::v3::InvitationRecipient::UserId {
+            user_id: user_id.to_owned(),
+        };
+        let mut request = invite_user::v3::Request::new(room.room_id().to_owned(), recipient);
+        request.reason = reason;
+
+        self.inner.send(request, None).await?;
+        Ok(self.membership(room_id, user_id, MembershipState::Invite))
+    }
+
+    pub(crate) async fn kick_user(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        reason: Option<&str>,
+    ) -> anyhow::Result<outputs::Membership> {
+        let room = self.get_joined_room(room_id)?;
+        room.kick_user(user_id, reason).await?;
+        Ok(self.membership(room_id, user_id, MembershipState::Leave))
+    }
+
+    pub(crate) async fn ban_user(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        reason: Option<&str>,
+    ) -> anyhow::Result<outputs::Membership> {
+        let room = self.get_joined_room(room_id)?;
+        room.ban_user(user_id, reason).await?;
+        Ok(self.membership(room_id, user_id, MembershipState::Ban))
+    }
+
+    pub(crate) async fn unban_user(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        reason: Option<&str>,
+    ) -> anyhow::Result<outputs::Membership> {
+        let room = self.get_joined_room(room_id)?;
+        room.unban_user(user_id, reason).await?;
+        Ok(self.membership(room_id, user_id, MembershipState::Leave))
+    }
+}
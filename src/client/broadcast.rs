@@ -0,0 +1,117 @@
+use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId, RoomId};
+
+use super::location::GeoUri;
+use super::mentions::MentionOptions;
+use super::resolve::RoomRef;
+use super::room::{MessageRelation, PreparedAttachment};
+use crate::attachment::Caption;
+
+/// A message, which can be sent to several rooms.
+pub(crate) enum Payload {
+    Text {
+        body: String,
+        markdown: bool,
+        notice: bool,
+        emote: bool,
+    },
+    Location {
+        geo_uri: GeoUri,
+        description: Option<String>,
+    },
+    /// The caption belongs to the first attachment.
+    Attachments {
+        attachments: Vec<PreparedAttachment>,
+        caption: Option<Caption>,
+    },
+}
+
+impl super::Client {
+    /// Collects the rooms given by ID, by tag or all joined rooms.
+    /// Each room is only returned once.
+    pub(crate) async fn broadcast_rooms(
+        &self,
+        rooms: &[RoomRef],
+        tags: &[String],
+        all_joined: bool,
+    ) -> anyhow::Result<Vec<OwnedRoomId>> {
+        let mut room_ids = Vec::new();
+        for room in rooms {
+            room_ids.push(self.resolve_room(room).await?);
+        }
+
+        for room in self.inner.joined_rooms() {
+            let tagged = match room.tags().await? {
+                Some(room_tags) if !tags.is_empty() => room_tags.keys().any(|t| {
+                    tags.iter()
+                        .any(|tag| t.as_ref() == tag || t.display_name() == tag)
+                }),
+                _ => false,
+            };
+            if all_joined || tagged {
+                room_ids.push(room.room_id().to_owned());
+            }
+        }
+
+        let mut seen = Vec::new();
+        room_ids.retain(|room_id| {
+            if seen.contains(room_id) {
+                return false;
+            }
+            seen.push(room_id.clone());
+            true
+        });
+        Ok(room_ids)
+    }
+
+    /// Sends the payload to a single room; attachments are only uploaded
+    /// for the first room with the same encryption setting.
+    pub(crate) async fn send_payload(
+        &self,
+        room_id: &RoomId,
+        payload: &mut Payload,
+        relation: Option<MessageRelation<'_>>,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<Vec<OwnedEventId>> {
+        let event_id = match payload {
+            Payload::Text {
+                body,
+                markdown,
+                notice,
+                emote,
+            } => {
+                if *notice {
+                    self.send_notice(room_id, body, *markdown, relation, mentions)
+                        .await?
+                } else if *emote {
+                    self.send_emote(room_id, body, *markdown, relation, mentions)
+                        .await?
+                } else {
+                    self.send_message(room_id, body, *markdown, relation, mentions)
+                        .await?
+                }
+            }
+            Payload::Location {
+                geo_uri,
+                description,
+            } => {
+                self.send_location(room_id, geo_uri, description.as_deref(), relation, mentions)
+                    .await?
+            }
+            Payload::Attachments {
+                attachments,
+                caption,
+            } => {
+                let mut event_ids = Vec::new();
+                for (i, attachment) in attachments.iter_mut().enumerate() {
+                    let caption = if i == 0 { caption.as_ref() } else { None };
+                    let event_id = self
+                        .send_attachment(room_id, attachment, caption, relation, mentions)
+                        .await?;
+                    event_ids.push(event_id);
+                }
+                return Ok(event_ids);
+            }
+        };
+        Ok(vec![event_id])
+    }
+}
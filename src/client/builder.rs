@@ -1,4 +1,5 @@
 use std::env;
+use std::sync::OnceLock;
 
 use anyhow::anyhow;
 use matrix_sdk::ruma::OwnedUserId;
@@ -43,19 +44,24 @@ impl ClientBuilder {
         let mut builder = MatrixClient::builder()
             .server_name(user_id.server_name())
             .sqlite_store(state_path, None);
+        let mut http_builder = reqwest::Client::builder();
 
         if let Ok(proxy) = env::var("HTTPS_PROXY") {
-            builder = builder.proxy(proxy);
+            builder = builder.proxy(&proxy);
+            http_builder = http_builder.proxy(reqwest::Proxy::all(proxy)?);
         }
 
         if env::var("MN_INSECURE").is_ok() {
             builder = builder.disable_ssl_verification();
+            http_builder = http_builder.danger_accept_invalid_certs(true);
         }
 
         let client = Client {
             inner: builder.build().await?,
+            http: http_builder.build()?,
             user_id,
             device_name,
+            upload_size_limit: OnceLock::new(),
         };
 
         client.connect().await?;
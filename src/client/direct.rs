@@ -0,0 +1,65 @@
+use matrix_sdk::ruma::events::direct::DirectEventContent;
+use matrix_sdk::ruma::events::room::member::MembershipState;
+use matrix_sdk::ruma::{OwnedRoomId, UserId};
+use matrix_sdk::RoomState;
+use tracing::info;
+
+use super::admin::{CreateRoomOptions, RoomPreset};
+
+impl super::Client {
+    /// Looks up a joined direct room with `user_id` in the `m.direct`
+    /// account data, where the user is still joined or invited.
+    async fn find_direct_room(&self, user_id: &UserId) -> anyhow::Result<Option<OwnedRoomId>> {
+        let Some(raw) = self
+            .inner
+            .account()
+            .account_data::<DirectEventContent>()
+            .await?
+        else {
+            return Ok(None);
+        };
+        let direct = raw.deserialize()?;
+        let Some(room_ids) = direct.get(user_id) else {
+            return Ok(None);
+        };
+
+        for room_id in room_ids {
+            let Some(room) = self.inner.get_room(room_id) else {
+                continue;
+            };
+            if room.state() != RoomState::Joined || !room.is_direct().await? {
+                continue;
+            }
+            let Some(member) = room.get_member(user_id).await? else {
+                continue;
+            };
+            if matches!(
+                member.membership(),
+                MembershipState::Join | MembershipState::Invite
+            ) {
+                return Ok(Some(room_id.clone()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the direct room with `user_id`. If there is none, an
+    /// encrypted room is created, the user invited and `m.direct` updated.
+    pub(crate) async fn direct_room(&self, user_id: &UserId) -> anyhow::Result<OwnedRoomId> {
+        if let Some(room_id) = self.find_direct_room(user_id).await? {
+            return Ok(room_id);
+        }
+
+        let options = CreateRoomOptions {
+            invite: vec![user_id.to_owned()],
+            preset: Some(RoomPreset::TrustedPrivate),
+            encrypted: true,
+            direct: true,
+            ..Default::default()
+        };
+        let room = self.create_room(options).await?;
+        info!("created direct room {} with {}", room.room_id, user_id);
+        Ok(room.room_id)
+    }
+}
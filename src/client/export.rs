@@ -0,0 +1,361 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use clap::ValueEnum;
+use matrix_sdk::deserialized_responses::TimelineEvent;
+use matrix_sdk::room::MessagesOptions;
+use matrix_sdk::ruma::events::{AnyMessageLikeEvent, AnyTimelineEvent, MessageLikeEvent};
+use matrix_sdk::ruma::{EventId, MilliSecondsSinceUnixEpoch, OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::media::message_media;
+use super::room::MESSAGES_PAGE_SIZE;
+use crate::util::{escape_html, sanitize_filename};
+
+const STATE_FILE: &str = "export.json";
+const MEDIA_DIR: &str = "media";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExportFormat {
+    Jsonl,
+    Html,
+    Txt,
+}
+
+impl ExportFormat {
+    fn file_name(&self) -> &'static str {
+        match self {
+            Self::Jsonl => "events.jsonl",
+            Self::Html => "events.html",
+            Self::Txt => "events.txt",
+        }
+    }
+}
+
+/// Progress of an export, stored in the output directory after each page.
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportState {
+    room_id: OwnedRoomId,
+    format: ExportFormat,
+    token: Option<String>,
+    /// Length of the events file after the last complete page; anything
+    /// beyond was written by an interrupted page and is dropped on resume.
+    #[serde(default)]
+    events_len: Option<u64>,
+    complete: bool,
+}
+
+impl ExportState {
+    fn load(dir: impl AsRef<Path>) -> anyhow::Result<Option<Self>> {
+        let path = dir.as_ref().join(STATE_FILE);
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    fn dump(&self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut raw = serde_json::to_string(&self)?;
+        if !raw.ends_with('\n') {
+            raw += "\n";
+        }
+        fs::write(dir.as_ref().join(STATE_FILE), raw)?;
+        Ok(())
+    }
+}
+
+/// A timeline event prepared for writing it to the export.
+struct ExportEvent {
+    raw: String,
+    sender: String,
+    timestamp: String,
+    event_type: String,
+    body: Option<String>,
+    media: Option<(String, bool)>,
+}
+
+fn format_timestamp(ts: MilliSecondsSinceUnixEpoch) -> String {
+    match ts.to_system_time() {
+        Some(t) => humantime::format_rfc3339_millis(t).to_string(),
+        None => ts.get().to_string(),
+    }
+}
+
+/// Names a downloaded media file after its event. The original file name
+/// is shortened, keeping its extension, to stay below filesystem limits.
+fn media_file_name(event_id: &EventId, filename: Option<&str>) -> String {
+    const MAX_NAME_LEN: usize = 64;
+
+    let mut name = sanitize_filename(filename.unwrap_or_default());
+    if name.len() > MAX_NAME_LEN {
+        let ext = Path::new(&name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .filter(|e| e.len() < 16)
+            .map(|e| format!(".{}", e))
+            .unwrap_or_default();
+        let mut end = MAX_NAME_LEN - ext.len();
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name = format!("{}{}", &name[..end], ext);
+    }
+    format!("{}-{}", sanitize_filename(event_id.as_str()), name)
+}
+
+fn write_header(
+    out: &mut impl Write,
+    format: ExportFormat,
+    room_id: &RoomId,
+) -> anyhow::Result<()> {
+    if format == ExportFormat::Html {
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html>")?;
+        writeln!(out, "<head>")?;
+        writeln!(out, "<meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>{}</title>", escape_html(room_id.as_str()))?;
+        writeln!(out, "</head>")?;
+        writeln!(out, "<body>")?;
+    }
+    Ok(())
+}
+
+fn write_footer(out: &mut impl Write, format: ExportFormat) -> anyhow::Result<()> {
+    if format == ExportFormat::Html {
+        writeln!(out, "</body>")?;
+        writeln!(out, "</html>")?;
+    }
+    Ok(())
+}
+
+fn write_event(out: &mut impl Write, format: ExportFormat, ev: &ExportEvent) -> anyhow::Result<()> {
+    match format {
+        ExportFormat::Jsonl => writeln!(out, "{}", ev.raw)?,
+        ExportFormat::Txt => {
+            let text = match (&ev.media, &ev.body) {
+                (Some((path, _)), Some(body)) => format!("{} [{}]", body, path),
+                (None, Some(body)) => body.to_owned(),
+                (_, None) => format!("[{}]", ev.event_type),
+            };
+            writeln!(out, "{} {}: {}", ev.timestamp, ev.sender, text)?;
+        }
+        ExportFormat::Html => {
+            let body = match (&ev.media, &ev.body) {
+                (Some((path, true)), Some(body)) => format!(
+                    "<a href=\"{0}\"><img src=\"{0}\" alt=\"{1}\"></a>",
+                    escape_html(path),
+                    escape_html(body)
+                ),
+                (Some((path, false)), Some(body)) => format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_html(path),
+                    escape_html(body)
+                ),
+                (None, Some(body)) => escape_html(body).replace('\n', "<br>"),
+                (_, None) => format!("<i>{}</i>", escape_html(&ev.event_type)),
+            };
+            writeln!(
+                out,
+                "<div class=\"event\"><span class=\"timestamp\">{}</span> <span class=\"sender\">{}</span> <span class=\"body\">{}</span></div>",
+                escape_html(&ev.timestamp),
+                escape_html(&ev.sender),
+                body,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+impl super::Client {
+    async fn prepare_export_event(
+        &self,
+        event: TimelineEvent,
+        media_dir: Option<&Path>,
+    ) -> anyhow::Result<ExportEvent> {
+        let raw = event.event.json().get().to_owned();
+        let parsed = match event.event.deserialize() {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                // Keep malformed events, e.g. from bridges, as raw JSON.
+                warn!("could not parse event: {}", e);
+                let field = |name| {
+                    event
+                        .event
+                        .get_field::<String>(name)
+                        .ok()
+                        .flatten()
+                        .unwrap_or_default()
+                };
+                let timestamp = event
+                    .event
+                    .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                    .ok()
+                    .flatten()
+                    .map(format_timestamp)
+                    .unwrap_or_default();
+                return Ok(ExportEvent {
+                    sender: field("sender"),
+                    timestamp,
+                    event_type: field("type"),
+                    raw,
+                    body: None,
+                    media: None,
+                });
+            }
+        };
+        let event_id = parsed.event_id().to_owned();
+
+        let mut out = ExportEvent {
+            raw,
+            sender: parsed.sender().to_string(),
+            timestamp: format_timestamp(parsed.origin_server_ts()),
+            event_type: parsed.event_type().to_string(),
+            body: None,
+            media: None,
+        };
+
+        let AnyTimelineEvent::MessageLike(message) = parsed else {
+            return Ok(out);
+        };
+
+        let msgtype = match message {
+            AnyMessageLikeEvent::RoomMessage(MessageLikeEvent::Original(ev)) => ev.content.msgtype,
+            AnyMessageLikeEvent::RoomMessage(MessageLikeEvent::Redacted(_)) => {
+                out.body = Some("[redacted]".to_owned());
+                return Ok(out);
+            }
+            AnyMessageLikeEvent::RoomEncrypted(_) => {
+                out.body = Some("[unable to decrypt]".to_owned());
+                return Ok(out);
+            }
+            _ => return Ok(out),
+        };
+
+        out.body = Some(msgtype.body().to_owned());
+
+        if let (Some(dir), Some((source, media))) =
+            (media_dir, message_media(&msgtype, &event.event))
+        {
+            let file_name = media_file_name(&event_id, media.filename.as_deref());
+            let is_image = media
+                .mimetype
+                .as_deref()
+                .is_some_and(|m| m.starts_with("image/"));
+
+            match self.download_media(source).await {
+                Ok(data) => {
+                    fs::write(dir.join(&file_name), data)?;
+                    out.media = Some((format!("{}/{}", MEDIA_DIR, file_name), is_image));
+                }
+                Err(e) => warn!("downloading {} failed: {}", media.mxc_uri, e),
+            }
+        }
+
+        Ok(out)
+    }
+
+    pub(crate) async fn export_room(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        format: ExportFormat,
+        out_dir: impl AsRef<Path>,
+        resume: bool,
+        media: bool,
+    ) -> anyhow::Result<()> {
+        let room_id = room_id.as_ref();
+        let out_dir = out_dir.as_ref();
+        let room = self.get_joined_room(room_id)?;
+
+        fs::create_dir_all(out_dir)?;
+
+        let (mut state, resumed) = match ExportState::load(out_dir)? {
+            Some(state) if resume => {
+                if state.room_id != room_id {
+                    bail!("export in {:?} belongs to room {}", out_dir, state.room_id);
+                }
+                if state.format != format {
+                    bail!("export in {:?} uses format {:?}", out_dir, state.format);
+                }
+                if state.complete {
+                    bail!("export in {:?} is already complete", out_dir);
+                }
+                (state, true)
+            }
+            Some(_) => bail!("export exists in {:?}; use --resume to continue", out_dir),
+            None => (
+                ExportState {
+                    room_id: room_id.to_owned(),
+                    format,
+                    token: None,
+                    events_len: None,
+                    complete: false,
+                },
+                false,
+            ),
+        };
+
+        let events_path: PathBuf = out_dir.join(format.file_name());
+        let mut out = if resumed {
+            if !events_path.try_exists()? {
+                bail!("export in {:?} has no {}", out_dir, format.file_name());
+            }
+            let mut out = OpenOptions::new().write(true).open(&events_path)?;
+            if let Some(len) = state.events_len {
+                out.set_len(len)?;
+            }
+            out.seek(SeekFrom::End(0))?;
+            out
+        } else {
+            let mut out = File::create(&events_path)?;
+            write_header(&mut out, format, room_id)?;
+            out
+        };
+
+        let media_dir = out_dir.join(MEDIA_DIR);
+        if media {
+            fs::create_dir_all(&media_dir)?;
+        }
+
+        let mut count = 0;
+        loop {
+            let mut options = MessagesOptions::forward().from(state.token.as_deref());
+            options.limit = MESSAGES_PAGE_SIZE.try_into()?;
+
+            let resp = room.messages(options).await?;
+
+            // The page is written at once after its media has been
+            // downloaded, so that the file matches the saved token.
+            let mut page = Vec::new();
+            for event in resp.chunk {
+                let event = self
+                    .prepare_export_event(event, media.then_some(media_dir.as_path()))
+                    .await?;
+                write_event(&mut page, format, &event)?;
+                count += 1;
+            }
+            out.write_all(&page)?;
+            out.flush()?;
+
+            match resp.end {
+                Some(end) => state.token = Some(end),
+                None => break,
+            }
+            state.events_len = Some(out.stream_position()?);
+            state.dump(out_dir)?;
+
+            info!("exported {} events", count);
+        }
+
+        write_footer(&mut out, format)?;
+        out.flush()?;
+
+        state.complete = true;
+        state.dump(out_dir)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+use matrix_sdk::room::Room;
+use matrix_sdk::ruma::events::room::member::{MembershipState, StrippedRoomMemberEvent};
+use matrix_sdk::ruma::{OwnedServerName, OwnedUserId, RoomId, UserId};
+use matrix_sdk::RoomState;
+
+use crate::outputs::{self, InviteAction};
+
+/// Matches the inviter by user ID (`@bot:example.org`) or server name
+/// (`example.org`), or the room ID with `*` as wildcard (`!*:example.org`).
+#[derive(Clone, Debug)]
+pub(crate) enum InviteRule {
+    User(OwnedUserId),
+    Server(OwnedServerName),
+    Room(String),
+}
+
+impl FromStr for InviteRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.chars().next() {
+            Some('@') => Ok(Self::User(UserId::parse(s)?)),
+            Some('!') => Ok(Self::Room(s.to_owned())),
+            Some(_) => Ok(Self::Server(OwnedServerName::try_from(s)?)),
+            None => bail!("empty invite rule"),
+        }
+    }
+}
+
+impl InviteRule {
+    fn matches(&self, room_id: &RoomId, inviter: &UserId) -> bool {
+        match self {
+            Self::User(user_id) => user_id == inviter,
+            Self::Server(server_name) => server_name == inviter.server_name(),
+            Self::Room(pattern) => wildcard_match(pattern, room_id.as_str()),
+        }
+    }
+}
+
+/// Matches `s` against `pattern`, where `*` matches any sequence.
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Decides which invites are joined automatically while syncing.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InvitePolicy {
+    pub(crate) rules: Vec<InviteRule>,
+    /// Reject invites which match no rule instead of ignoring them.
+    pub(crate) reject: bool,
+}
+
+impl InvitePolicy {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty() && !self.reject
+    }
+
+    fn decide(&self, room_id: &RoomId, inviter: &UserId) -> InviteAction {
+        if self.rules.iter().any(|r| r.matches(room_id, inviter)) {
+            InviteAction::Join
+        } else if self.reject {
+            InviteAction::Reject
+        } else {
+            InviteAction::Ignore
+        }
+    }
+}
+
+async fn on_invite(
+    event: StrippedRoomMemberEvent,
+    room: Room,
+    own_user_id: &UserId,
+    policy: &InvitePolicy,
+) -> anyhow::Result<()> {
+    if event.state_key != own_user_id
+        || event.content.membership != MembershipState::Invite
+        || room.state() != RoomState::Invited
+    {
+        return Ok(());
+    }
+
+    let action = policy.decide(room.room_id(), &event.sender);
+    let res = match action {
+        InviteAction::Join => room.join().await,
+        InviteAction::Reject => room.leave().await,
+        InviteAction::Ignore => Ok(()),
+    };
+
+    let decision = outputs::InviteDecision {
+        room_id: room.room_id().to_owned(),
+        inviter: event.sender,
+        action,
+        error: res.err().map(|e| e.to_string()),
+    };
+    println!("{}", serde_json::to_string(&decision)?);
+    Ok(())
+}
+
+impl super::Client {
+    /// Handles invites according to `policy` during the following syncs.
+    /// Each decision is printed as JSON line.
+    pub(crate) fn handle_invites(&self, policy: InvitePolicy) {
+        let own_user_id = self.user_id.clone();
+        self.inner
+            .add_event_handler(move |event: StrippedRoomMemberEvent, room: Room| {
+                let own_user_id = own_user_id.clone();
+                let policy = policy.clone();
+                async move { on_invite(event, room, &own_user_id, &policy).await }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::{room_id, user_id};
+
+    use super::*;
+
+    #[test]
+    fn wildcard_literal() {
+        assert!(wildcard_match("!abc:example.org", "!abc:example.org"));
+        assert!(!wildcard_match("!abc:example.org", "!abcd:example.org"));
+        assert!(!wildcard_match("!abc:example.org", "!ab:example.org"));
+    }
+
+    #[test]
+    fn wildcard_prefix_and_suffix() {
+        assert!(wildcard_match("!*:example.org", "!abc:example.org"));
+        assert!(!wildcard_match("!*:example.org", "!abc:example.com"));
+        assert!(wildcard_match("!abc*", "!abc:example.org"));
+        assert!(!wildcard_match("!abc*", "!ab:example.org"));
+        assert!(wildcard_match("*", "!abc:example.org"));
+        assert!(wildcard_match("*:example.org", "!abc:example.org"));
+    }
+
+    #[test]
+    fn wildcard_middle() {
+        assert!(wildcard_match("!a*c:example.org", "!abc:example.org"));
+        assert!(wildcard_match("!a*c:example.org", "!ac:example.org"));
+        assert!(!wildcard_match("!a*c:example.org", "!abd:example.org"));
+        assert!(wildcard_match("!*b*:example.org", "!abc:example.org"));
+        assert!(!wildcard_match("!*x*:example.org", "!abc:example.org"));
+    }
+
+    #[test]
+    fn wildcard_overlapping() {
+        // The prefix and suffix must not share characters.
+        assert!(!wildcard_match("ab*ba", "aba"));
+        assert!(wildcard_match("ab*ba", "abba"));
+        assert!(!wildcard_match("a*a", "a"));
+        assert!(wildcard_match("a*a*a", "aaa"));
+        assert!(!wildcard_match("a*a*a", "aa"));
+        assert!(wildcard_match("**", ""));
+    }
+
+    #[test]
+    fn parse_rules() {
+        let rule: InviteRule = "@admin:example.org".parse().unwrap();
+        assert!(matches!(rule, InviteRule::User(u) if u == "@admin:example.org"));
+
+        let rule: InviteRule = "example.org".parse().unwrap();
+        assert!(matches!(rule, InviteRule::Server(s) if s == "example.org"));
+
+        let rule: InviteRule = "!*:example.org".parse().unwrap();
+        assert!(matches!(rule, InviteRule::Room(p) if p == "!*:example.org"));
+
+        assert!("".parse::<InviteRule>().is_err());
+        assert!("@admin".parse::<InviteRule>().is_err());
+        assert!("not a server".parse::<InviteRule>().is_err());
+    }
+
+    #[test]
+    fn rule_matches() {
+        let room_id = room_id!("!abc:example.org");
+        let inviter = user_id!("@admin:example.org");
+
+        let rule: InviteRule = "@admin:example.org".parse().unwrap();
+        assert!(rule.matches(room_id, inviter));
+        assert!(!rule.matches(room_id, user_id!("@admin:example.com")));
+
+        let rule: InviteRule = "example.org".parse().unwrap();
+        assert!(rule.matches(room_id, inviter));
+        assert!(!rule.matches(room_id, user_id!("@admin:evil.org")));
+
+        let rule: InviteRule = "!*:example.org".parse().unwrap();
+        assert!(rule.matches(room_id, inviter));
+        assert!(!rule.matches(room_id!("!abc:evil.org"), inviter));
+    }
+
+    #[test]
+    fn policy_decisions() {
+        let room_id = room_id!("!abc:example.org");
+        let policy = InvitePolicy {
+            rules: vec!["example.org".parse().unwrap()],
+            reject: false,
+        };
+        assert!(matches!(
+            policy.decide(room_id, user_id!("@a:example.org")),
+            InviteAction::Join
+        ));
+        assert!(matches!(
+            policy.decide(room_id, user_id!("@a:evil.org")),
+            InviteAction::Ignore
+        ));
+
+        let policy = InvitePolicy {
+            reject: true,
+            ..policy
+        };
+        assert!(matches!(
+            policy.decide(room_id, user_id!("@a:evil.org")),
+            InviteAction::Reject
+        ));
+    }
+}
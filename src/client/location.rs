@@ -0,0 +1,272 @@
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use futures::channel::mpsc;
+use futures::StreamExt;
+use matrix_sdk::room::Room;
+use matrix_sdk::ruma::events::room::message::{
+    LocationMessageEventContent, MessageType, RoomMessageEventContent,
+};
+use matrix_sdk::ruma::{EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, RoomId};
+use serde_json::json;
+use tracing::{info, warn};
+
+use super::mentions::MentionOptions;
+use super::room::MessageRelation;
+use crate::outputs;
+
+// Live locations are not supported by ruma without the unstable
+// MSC3488/MSC3489 features; the events are built by hand.
+const BEACON_INFO_TYPE: &str = "org.matrix.msc3672.beacon_info";
+const BEACON_TYPE: &str = "org.matrix.msc3672.beacon";
+
+/// A `geo:` URI as defined by RFC 5870, e.g. `geo:52.52,13.40;u=35`.
+#[derive(Clone, Debug)]
+pub(crate) struct GeoUri(String);
+
+impl FromStr for GeoUri {
+    type Err = anyhow::Error;
+
+    /// Parses a `geo:` URI; the scheme may be omitted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let uri = s.strip_prefix("geo:").unwrap_or(s);
+        let mut parts = uri.split(';');
+        let coords = parts.next().unwrap_or_default();
+
+        let coords = coords
+            .split(',')
+            .map(|c| c.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("invalid coordinates {:?}: {}", coords, e))?;
+        let (latitude, longitude) = match coords[..] {
+            [lat, lon] | [lat, lon, _] => (lat, lon),
+            _ => bail!("expected latitude,longitude[,altitude]: {:?}", s),
+        };
+        if !(-90.0..=90.0).contains(&latitude) {
+            bail!("latitude out of range: {}", latitude);
+        }
+        if !(-180.0..=180.0).contains(&longitude) {
+            bail!("longitude out of range: {}", longitude);
+        }
+
+        for param in parts {
+            if let Some(uncertainty) = param.strip_prefix("u=") {
+                match uncertainty.parse::<f64>() {
+                    Ok(u) if u >= 0.0 => {}
+                    _ => bail!("invalid uncertainty: {:?}", uncertainty),
+                }
+            }
+        }
+
+        Ok(Self(format!("geo:{}", uri)))
+    }
+}
+
+impl fmt::Display for GeoUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A running live location share, which is announced by a beacon info
+/// state event and updated by beacons referencing it.
+struct LiveLocation {
+    room: Room,
+    description: Option<String>,
+    timeout: Duration,
+    ts: MilliSecondsSinceUnixEpoch,
+}
+
+fn print_sent(live: &LiveLocation, event_id: OwnedEventId) -> anyhow::Result<()> {
+    let sent = outputs::SentEvent {
+        room_id: live.room.room_id().to_owned(),
+        event_id,
+    };
+    println!("{}", serde_json::to_string(&sent)?);
+    Ok(())
+}
+
+/// Reads stdin line by line on a plain thread. A read on tokio's blocking
+/// pool cannot be cancelled and would keep the process alive until the
+/// next line arrives, even after sharing has stopped.
+fn stdin_lines() -> mpsc::UnboundedReceiver<io::Result<String>> {
+    let (tx, rx) = mpsc::unbounded();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            if tx.unbounded_send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+impl super::Client {
+    pub(crate) async fn send_location(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        geo_uri: &GeoUri,
+        description: Option<&str>,
+        relation: Option<MessageRelation<'_>>,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<OwnedEventId> {
+        let body = match description {
+            Some(description) => description.to_owned(),
+            None => format!("Location {}", geo_uri),
+        };
+        let content = LocationMessageEventContent::new(body, geo_uri.to_string());
+        let content = RoomMessageEventContent::new(MessageType::Location(content));
+        self.send_message_raw(room_id, content, relation, mentions)
+            .await
+    }
+
+    async fn send_beacon_info(
+        &self,
+        live: &LiveLocation,
+        is_live: bool,
+    ) -> anyhow::Result<OwnedEventId> {
+        let mut content = json!({
+            "live": is_live,
+            "timeout": live.timeout.as_millis() as u64,
+            "org.matrix.msc3488.ts": live.ts,
+            "org.matrix.msc3488.asset": { "type": "m.self" },
+        });
+        if let Some(description) = &live.description {
+            content["description"] = description.as_str().into();
+        }
+
+        let resp = live
+            .room
+            .send_state_event_raw(BEACON_INFO_TYPE, self.user_id.as_str(), content)
+            .await?;
+        Ok(resp.event_id)
+    }
+
+    async fn send_beacon(
+        &self,
+        live: &LiveLocation,
+        beacon_info: &EventId,
+        geo_uri: &GeoUri,
+    ) -> anyhow::Result<OwnedEventId> {
+        let content = json!({
+            "m.relates_to": {
+                "rel_type": "m.reference",
+                "event_id": beacon_info,
+            },
+            "org.matrix.msc3488.location": { "uri": geo_uri.to_string() },
+            "org.matrix.msc3488.ts": MilliSecondsSinceUnixEpoch::now(),
+        });
+        let resp = live.room.send_raw(BEACON_TYPE, content).await?;
+        Ok(resp.event_id)
+    }
+
+    async fn update_live_location(
+        &self,
+        live: &LiveLocation,
+        beacon_info: &EventId,
+    ) -> anyhow::Result<()> {
+        let mut lines = stdin_lines();
+        let deadline = tokio::time::sleep(live.timeout);
+        tokio::pin!(deadline);
+        // Created once, so that an interrupt while sending is not lost.
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+
+        loop {
+            tokio::select! {
+                line = lines.next() => {
+                    let Some(line) = line else {
+                        return Ok(());
+                    };
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match line.parse::<GeoUri>() {
+                        Ok(geo_uri) => {
+                            let event_id = self.send_beacon(live, beacon_info, &geo_uri).await?;
+                            print_sent(live, event_id)?;
+                        }
+                        Err(e) => warn!("ignoring location {:?}: {}", line, e),
+                    }
+                }
+                _ = &mut deadline => {
+                    info!("live location timed out");
+                    return Ok(());
+                }
+                res = &mut ctrl_c => return Ok(res?),
+            }
+        }
+    }
+
+    /// Shares the locations read line by line from stdin until stdin is
+    /// closed, the timeout expires or the process is interrupted.
+    pub(crate) async fn share_live_location(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        description: Option<&str>,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        let room = self.get_joined_room(room_id)?;
+        let live = LiveLocation {
+            room,
+            description: description.map(ToOwned::to_owned),
+            timeout,
+            ts: MilliSecondsSinceUnixEpoch::now(),
+        };
+        let beacon_info = self.send_beacon_info(&live, true).await?;
+        print_sent(&live, beacon_info.clone())?;
+
+        let res = self.update_live_location(&live, &beacon_info).await;
+
+        // Stop sharing even if sending a beacon failed.
+        let event_id = self.send_beacon_info(&live, false).await?;
+        print_sent(&live, event_id)?;
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> anyhow::Result<String> {
+        s.parse::<GeoUri>().map(|uri| uri.to_string())
+    }
+
+    #[test]
+    fn valid_uris() {
+        assert_eq!(parse("geo:52.52,13.40").unwrap(), "geo:52.52,13.40");
+        assert_eq!(parse("52.52,13.40").unwrap(), "geo:52.52,13.40");
+        assert_eq!(parse(" geo:52.52,13.40 ").unwrap(), "geo:52.52,13.40");
+        assert_eq!(parse("geo:52.52,13.40,34").unwrap(), "geo:52.52,13.40,34");
+        assert_eq!(
+            parse("geo:52.52,13.40;u=35").unwrap(),
+            "geo:52.52,13.40;u=35"
+        );
+        assert_eq!(parse("geo:-90,180").unwrap(), "geo:-90,180");
+    }
+
+    #[test]
+    fn invalid_coordinates() {
+        assert!(parse("").is_err());
+        assert!(parse("geo:").is_err());
+        assert!(parse("geo:52.52").is_err());
+        assert!(parse("geo:52.52,13.40,34,1").is_err());
+        assert!(parse("geo:north,east").is_err());
+        assert!(parse("geo:90.1,13.40").is_err());
+        assert!(parse("geo:52.52,-180.1").is_err());
+    }
+
+    #[test]
+    fn invalid_uncertainty() {
+        assert!(parse("geo:52.52,13.40;u=-1").is_err());
+        assert!(parse("geo:52.52,13.40;u=far").is_err());
+        assert!(parse("geo:52.52,13.40;crs=wgs84").is_ok());
+    }
+}
@@ -0,0 +1,313 @@
+use std::io::{Cursor, Read};
+
+use anyhow::bail;
+use clap::ValueEnum;
+use matrix_sdk::ruma::api::client::media::get_media_config;
+use matrix_sdk::ruma::events::room::message::{MessageType, RoomMessageEvent};
+use matrix_sdk::ruma::events::room::MediaSource;
+use matrix_sdk::ruma::events::AnyTimelineEvent;
+use matrix_sdk::ruma::serde::Raw;
+use matrix_sdk::ruma::{EventId, MxcUri, RoomId};
+use matrix_sdk_crypto::AttachmentDecryptor;
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::outputs;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum ThumbnailMethod {
+    Crop,
+    Scale,
+}
+
+impl ThumbnailMethod {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Crop => "crop",
+            Self::Scale => "scale",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Thumbnail {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) method: ThumbnailMethod,
+}
+
+/// A file fetched from the media repository.
+pub(crate) struct MediaResponse {
+    pub(crate) data: Vec<u8>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) content_disposition: Option<String>,
+}
+
+/// Reads `content.filename` from the raw event. It is only modelled by
+/// ruma for `m.file`, but may be set on all media messages to distinguish
+/// the file name from a caption in `body`.
+fn raw_filename(event: &Raw<AnyTimelineEvent>) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Content {
+        filename: Option<String>,
+    }
+
+    let content = event.get_field::<Content>("content").ok().flatten()?;
+    content.filename
+}
+
+/// Extracts the media source and its metadata from a message, if it
+/// references a file.
+pub(crate) fn message_media(
+    msgtype: &MessageType,
+    event: &Raw<AnyTimelineEvent>,
+) -> Option<(MediaSource, outputs::Media)> {
+    let (source, body, mimetype, size) = match msgtype {
+        MessageType::Audio(c) => (
+            &c.source,
+            &c.body,
+            c.info.as_ref().and_then(|i| i.mimetype.clone()),
+            c.info.as_ref().and_then(|i| i.size),
+        ),
+        MessageType::File(c) => (
+            &c.source,
+            &c.body,
+            c.info.as_ref().and_then(|i| i.mimetype.clone()),
+            c.info.as_ref().and_then(|i| i.size),
+        ),
+        MessageType::Image(c) => (
+            &c.source,
+            &c.body,
+            c.info.as_ref().and_then(|i| i.mimetype.clone()),
+            c.info.as_ref().and_then(|i| i.size),
+        ),
+        MessageType::Video(c) => (
+            &c.source,
+            &c.body,
+            c.info.as_ref().and_then(|i| i.mimetype.clone()),
+            c.info.as_ref().and_then(|i| i.size),
+        ),
+        _ => return None,
+    };
+
+    let media = outputs::Media {
+        mxc_uri: match source {
+            MediaSource::Plain(uri) => uri.to_string(),
+            MediaSource::Encrypted(file) => file.url.to_string(),
+        },
+        filename: Some(raw_filename(event).unwrap_or_else(|| body.clone())),
+        mimetype,
+        size: size.map(u64::from),
+        encrypted: matches!(source, MediaSource::Encrypted(_)),
+    };
+
+    Some((source.clone(), media))
+}
+
+/// Extracts the file name from a `Content-Disposition` header.
+fn disposition_filename(disposition: &str) -> Option<String> {
+    disposition.split(';').find_map(|param| {
+        let (key, value) = param.trim().split_once('=')?;
+        if key.trim().eq_ignore_ascii_case("filename") {
+            Some(value.trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+impl super::Client {
+    fn media_path(mxc: &MxcUri, thumbnail: Option<&Thumbnail>) -> anyhow::Result<String> {
+        let (server_name, media_id) = mxc.parts()?;
+        Ok(match thumbnail {
+            Some(t) => format!(
+                "thumbnail/{}/{}?width={}&height={}&method={}",
+                server_name,
+                media_id,
+                t.width,
+                t.height,
+                t.method.as_str()
+            ),
+            None => format!("download/{}/{}", server_name, media_id),
+        })
+    }
+
+    /// Builds a URL for the authenticated media API. Fetching it requires
+    /// the access token as bearer token.
+    pub(crate) fn media_url(
+        &self,
+        mxc: &MxcUri,
+        thumbnail: Option<&Thumbnail>,
+    ) -> anyhow::Result<String> {
+        let path = Self::media_path(mxc, thumbnail)?;
+        Ok(format!(
+            "{}_matrix/client/v1/media/{}",
+            self.inner.homeserver().as_str(),
+            path
+        ))
+    }
+
+    /// Fetches a file via the authenticated media API. Homeservers which
+    /// do not yet support it are queried via the legacy endpoints.
+    pub(crate) async fn fetch_media(
+        &self,
+        mxc: &MxcUri,
+        thumbnail: Option<&Thumbnail>,
+    ) -> anyhow::Result<MediaResponse> {
+        let Some(token) = self.inner.access_token() else {
+            bail!("client not logged in");
+        };
+
+        let mut resp = self
+            .http
+            .get(self.media_url(mxc, thumbnail)?)
+            .bearer_auth(token)
+            .send()
+            .await?;
+
+        if matches!(
+            resp.status(),
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+        ) {
+            let url = format!(
+                "{}_matrix/media/v3/{}",
+                self.inner.homeserver().as_str(),
+                Self::media_path(mxc, thumbnail)?
+            );
+            resp = self.http.get(url).send().await?;
+        }
+
+        let resp = resp.error_for_status()?;
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+
+        Ok(MediaResponse {
+            content_type: header(CONTENT_TYPE),
+            content_disposition: header(CONTENT_DISPOSITION),
+            data: resp.bytes().await?.to_vec(),
+        })
+    }
+
+    /// Downloads a file via the authenticated media API and decrypts it,
+    /// if it is encrypted.
+    pub(crate) async fn download_media(&self, source: MediaSource) -> anyhow::Result<Vec<u8>> {
+        match source {
+            MediaSource::Plain(mxc) => Ok(self.fetch_media(&mxc, None).await?.data),
+            MediaSource::Encrypted(file) => {
+                let ciphertext = self.fetch_media(&file.url, None).await?.data;
+                let mut reader = Cursor::new(ciphertext);
+                let mut decryptor = AttachmentDecryptor::new(&mut reader, (*file).into())?;
+                let mut data = Vec::new();
+                decryptor.read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    pub(crate) async fn download_mxc(
+        &self,
+        mxc: &MxcUri,
+    ) -> anyhow::Result<(Vec<u8>, outputs::Media)> {
+        if !mxc.is_valid() {
+            bail!("invalid mxc uri: {}", mxc);
+        }
+
+        let resp = self.fetch_media(mxc, None).await?;
+        let media = outputs::Media {
+            mxc_uri: mxc.to_string(),
+            filename: resp
+                .content_disposition
+                .as_deref()
+                .and_then(disposition_filename),
+            mimetype: resp.content_type,
+            size: Some(resp.data.len() as u64),
+            encrypted: false,
+        };
+
+        Ok((resp.data, media))
+    }
+
+    pub(crate) async fn download_event_media(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_id: &EventId,
+    ) -> anyhow::Result<(Vec<u8>, outputs::Media)> {
+        let room = self.get_joined_room(room_id)?;
+        let timeline_event = room.event(event_id).await?;
+        let event = timeline_event.event.deserialize_as::<RoomMessageEvent>()?;
+
+        let Some(event) = event.as_original() else {
+            bail!("event {} is redacted", event_id);
+        };
+        let Some((source, mut media)) =
+            message_media(&event.content.msgtype, &timeline_event.event)
+        else {
+            bail!("event {} does not reference a file", event_id);
+        };
+
+        let data = self.download_media(source).await?;
+        media.size = Some(data.len() as u64);
+
+        Ok((data, media))
+    }
+
+    /// Queries the maximum upload size of the homeserver. The result is
+    /// cached, so that sending many attachments needs only one request.
+    async fn upload_size_limit(&self) -> anyhow::Result<u64> {
+        if let Some(limit) = self.upload_size_limit.get() {
+            return Ok(*limit);
+        }
+        let resp = self
+            .inner
+            .send(get_media_config::v3::Request::new(), None)
+            .await?;
+        Ok(*self
+            .upload_size_limit
+            .get_or_init(|| resp.upload_size.into()))
+    }
+
+    pub(crate) async fn upload_media(
+        &self,
+        content_type: &mime::Mime,
+        data: Vec<u8>,
+        encrypt: bool,
+    ) -> anyhow::Result<outputs::Upload> {
+        let size = data.len() as u64;
+        match self.upload_size_limit().await {
+            Ok(limit) if size > limit => {
+                bail!(
+                    "file too large: {} bytes; server allows {} bytes",
+                    size,
+                    limit
+                )
+            }
+            Ok(_) => {}
+            Err(e) => warn!("could not query upload size limit: {}", e),
+        }
+
+        let (mxc_uri, encryption) = if encrypt {
+            let mut cursor = Cursor::new(data);
+            let file = self
+                .inner
+                .prepare_encrypted_file(content_type, &mut cursor)
+                .await?;
+            (file.url.clone(), Some(file))
+        } else {
+            let resp = self.inner.media().upload(content_type, data).await?;
+            (resp.content_uri, None)
+        };
+
+        Ok(outputs::Upload {
+            mxc_uri,
+            mimetype: content_type.essence_str().to_owned(),
+            size,
+            encryption,
+        })
+    }
+}
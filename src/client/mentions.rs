@@ -0,0 +1,124 @@
+use matrix_sdk::room::Room;
+use matrix_sdk::ruma::events::room::message::{
+    FormattedBody, MessageType, RoomMessageEventContent,
+};
+use matrix_sdk::ruma::events::Mentions;
+use matrix_sdk::ruma::OwnedUserId;
+use tracing::warn;
+
+use crate::util::escape_html;
+
+/// Users or the whole room, which are intentionally mentioned by a message.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MentionOptions {
+    pub(crate) users: Vec<OwnedUserId>,
+    pub(crate) room: bool,
+}
+
+impl MentionOptions {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.users.is_empty() && !self.room
+    }
+
+    fn to_mentions(&self) -> Mentions {
+        let mut mentions = Mentions::with_user_ids(self.users.iter().cloned());
+        mentions.room = self.room;
+        mentions
+    }
+}
+
+/// The mentions rendered as text, which is prepended to the message body.
+#[derive(Debug, Default)]
+pub(crate) struct Pills {
+    plain: String,
+    html: String,
+}
+
+impl Pills {
+    pub(super) fn apply(&self, body: &mut String, formatted: &mut Option<FormattedBody>) {
+        if self.plain.is_empty() {
+            return;
+        }
+
+        let html = match formatted.take() {
+            Some(formatted) => formatted.body,
+            None => escape_html(body).replace('\n', "<br>"),
+        };
+        *formatted = Some(FormattedBody::html(format!("{}: {}", self.html, html)));
+        *body = format!("{}: {}", self.plain, body);
+    }
+}
+
+impl super::Client {
+    /// Renders the mentioned users as links with their display names.
+    pub(super) async fn pills(
+        &self,
+        room: &Room,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<Pills> {
+        let mut plain = Vec::new();
+        let mut html = Vec::new();
+
+        if mentions.room {
+            plain.push("@room".to_owned());
+            html.push("@room".to_owned());
+        }
+
+        for user_id in &mentions.users {
+            let name = match room.get_member(user_id).await? {
+                Some(member) => member.name().to_owned(),
+                None => {
+                    warn!("{} is not a member of {}", user_id, room.room_id());
+                    user_id.to_string()
+                }
+            };
+            html.push(format!(
+                "<a href=\"{}\">{}</a>",
+                user_id.matrix_to_uri(),
+                escape_html(&name)
+            ));
+            plain.push(name);
+        }
+
+        Ok(Pills {
+            plain: plain.join(", "),
+            html: html.join(", "),
+        })
+    }
+
+    /// Sets `m.mentions` and prepends pills to text messages.
+    pub(super) async fn mention(
+        &self,
+        room: &Room,
+        content: RoomMessageEventContent,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<RoomMessageEventContent> {
+        if mentions.is_empty() {
+            return Ok(content);
+        }
+
+        let pills = self.pills(room, mentions).await?;
+        Ok(add_mentions(content, mentions, &pills))
+    }
+}
+
+/// Sets `m.mentions` and prepends the already rendered pills to text
+/// messages.
+pub(super) fn add_mentions(
+    mut content: RoomMessageEventContent,
+    mentions: &MentionOptions,
+    pills: &Pills,
+) -> RoomMessageEventContent {
+    if mentions.is_empty() {
+        return content;
+    }
+
+    match &mut content.msgtype {
+        MessageType::Text(c) => pills.apply(&mut c.body, &mut c.formatted),
+        MessageType::Notice(c) => pills.apply(&mut c.body, &mut c.formatted),
+        MessageType::Emote(c) => pills.apply(&mut c.body, &mut c.formatted),
+        _ => {}
+    }
+
+    content.add_mentions(mentions.to_mentions())
+}
@@ -1,4 +1,5 @@
 use std::ops::Deref;
+use std::sync::OnceLock;
 
 use matrix_sdk::ruma::{OwnedDeviceId, OwnedUserId};
 use matrix_sdk::Client as MatrixClient;
@@ -6,11 +7,23 @@ use serde::Serialize;
 
 use crate::CRATE_NAME;
 
+pub mod admin;
+pub mod broadcast;
 pub mod builder;
+pub mod direct;
+pub mod export;
+pub mod invites;
+pub mod location;
 pub mod login;
+pub mod media;
+pub mod mentions;
+pub mod poll;
+pub mod relations;
+pub mod resolve;
 pub mod room;
 pub mod sas;
 pub mod session;
+pub mod state;
 
 // Copy of the ruma Response type; the origninal type does not
 // implement Serialize.
@@ -24,8 +37,12 @@ pub(crate) struct WhoamiResponse {
 
 pub(crate) struct Client {
     inner: MatrixClient,
+    // Used for endpoints which are not supported by the matrix-sdk.
+    http: reqwest::Client,
     user_id: OwnedUserId,
     device_name: String,
+    // The homeserver's upload limit, queried once on the first upload.
+    upload_size_limit: OnceLock<u64>,
 }
 
 impl Client {
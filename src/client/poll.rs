@@ -0,0 +1,214 @@
+use anyhow::bail;
+use matrix_sdk::room::Room;
+use matrix_sdk::ruma::events::poll::compile_unstable_poll_results;
+use matrix_sdk::ruma::events::poll::start::PollKind;
+use matrix_sdk::ruma::events::poll::unstable_end::{
+    OriginalSyncUnstablePollEndEvent, UnstablePollEndEventContent,
+};
+use matrix_sdk::ruma::events::poll::unstable_response::{
+    OriginalSyncUnstablePollResponseEvent, UnstablePollResponseEventContent,
+};
+use matrix_sdk::ruma::events::poll::unstable_start::{
+    NewUnstablePollStartEventContent, OriginalSyncUnstablePollStartEvent, UnstablePollAnswer,
+    UnstablePollAnswers, UnstablePollStartContentBlock,
+};
+use matrix_sdk::ruma::events::relation::RelationType;
+use matrix_sdk::ruma::{EventId, OwnedEventId, RoomId, UInt};
+
+use crate::outputs;
+
+// Polls use the unstable MSC3381 event types, since these are the ones
+// which Element and most other clients understand.
+const POLL_START_TYPE: &str = "org.matrix.msc3381.poll.start";
+const POLL_RESPONSE_TYPE: &str = "org.matrix.msc3381.poll.response";
+const POLL_END_TYPE: &str = "org.matrix.msc3381.poll.end";
+
+#[derive(Debug)]
+pub(crate) struct PollOptions {
+    pub(crate) question: String,
+    pub(crate) answers: Vec<String>,
+    pub(crate) disclosed: bool,
+    pub(crate) max_selections: u32,
+}
+
+/// A poll with all its responses and the end event, if it has ended.
+struct Poll {
+    start: OriginalSyncUnstablePollStartEvent,
+    responses: Vec<OriginalSyncUnstablePollResponseEvent>,
+    end: Option<OriginalSyncUnstablePollEndEvent>,
+}
+
+impl super::Client {
+    async fn poll(&self, room: &Room, event_id: &EventId) -> anyhow::Result<Poll> {
+        let event = room.event(event_id).await?.event;
+        if event.get_field::<String>("type")?.as_deref() != Some(POLL_START_TYPE) {
+            bail!("event {} is not a poll", event_id);
+        }
+        let start: OriginalSyncUnstablePollStartEvent = event.deserialize_as()?;
+
+        let responses = self
+            .related_events(room, event_id, RelationType::Reference, POLL_RESPONSE_TYPE)
+            .await?;
+        // Only the creator of a poll may end it.
+        let end = self
+            .related_events::<OriginalSyncUnstablePollEndEvent>(
+                room,
+                event_id,
+                RelationType::Reference,
+                POLL_END_TYPE,
+            )
+            .await?
+            .into_iter()
+            .filter(|e| e.sender == start.sender)
+            .min_by_key(|e| e.origin_server_ts);
+
+        Ok(Poll {
+            start,
+            responses,
+            end,
+        })
+    }
+
+    pub(crate) async fn create_poll(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        options: &PollOptions,
+    ) -> anyhow::Result<OwnedEventId> {
+        let room = self.get_joined_room(room_id)?;
+
+        let answers: Vec<UnstablePollAnswer> = options
+            .answers
+            .iter()
+            .enumerate()
+            .map(|(i, text)| UnstablePollAnswer::new((i + 1).to_string(), text))
+            .collect();
+        let answers = match UnstablePollAnswers::try_from(answers) {
+            Ok(answers) => answers,
+            Err(e) => bail!("invalid answers: {}", e),
+        };
+        if options.max_selections == 0 || options.max_selections as usize > answers.len() {
+            bail!(
+                "max selections must be between 1 and the number of answers ({})",
+                answers.len()
+            );
+        }
+
+        let mut poll = UnstablePollStartContentBlock::new(&options.question, answers);
+        poll.max_selections = UInt::from(options.max_selections);
+        poll.kind = if options.disclosed {
+            PollKind::Disclosed
+        } else {
+            PollKind::Undisclosed
+        };
+
+        let text = std::iter::once(options.question.clone())
+            .chain(poll.answers.iter().map(|a| format!("{}. {}", a.id, a.text)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let content = NewUnstablePollStartEventContent::plain_text(text, poll);
+
+        let resp = room.send(content).await?;
+        Ok(resp.event_id)
+    }
+
+    /// Votes for the given answers; answers are matched by ID or by text.
+    pub(crate) async fn vote_poll(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_id: &EventId,
+        answers: &[String],
+    ) -> anyhow::Result<OwnedEventId> {
+        let room = self.get_joined_room(room_id)?;
+        let poll = self.poll(&room, event_id).await?;
+        if poll.end.is_some() {
+            bail!("poll {} has ended", event_id);
+        }
+
+        let poll_start = poll.start.content.poll_start();
+        let mut selections = Vec::new();
+        for answer in answers {
+            let Some(found) = poll_start
+                .answers
+                .iter()
+                .find(|a| &a.id == answer || &a.text == answer)
+            else {
+                bail!("poll {} has no answer {}", event_id, answer);
+            };
+            selections.push(found.id.clone());
+        }
+        if UInt::try_from(selections.len())? > poll_start.max_selections {
+            bail!(
+                "poll {} allows at most {} answers",
+                event_id,
+                poll_start.max_selections
+            );
+        }
+
+        let content = UnstablePollResponseEventContent::new(selections, event_id.to_owned());
+        let resp = room.send(content).await?;
+        Ok(resp.event_id)
+    }
+
+    pub(crate) async fn end_poll(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_id: &EventId,
+    ) -> anyhow::Result<OwnedEventId> {
+        let room = self.get_joined_room(room_id)?;
+        let poll = self.poll(&room, event_id).await?;
+        if poll.start.sender != self.user_id {
+            bail!("poll {} was created by {}", event_id, poll.start.sender);
+        }
+        if poll.end.is_some() {
+            bail!("poll {} has already ended", event_id);
+        }
+
+        let content: UnstablePollEndEventContent = poll
+            .start
+            .compile_results(poll.responses.iter().map(|r| r.data()));
+        let resp = room.send(content).await?;
+        Ok(resp.event_id)
+    }
+
+    pub(crate) async fn poll_results(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_id: &EventId,
+    ) -> anyhow::Result<outputs::PollResults> {
+        let room = self.get_joined_room(room_id)?;
+        let poll = self.poll(&room, event_id).await?;
+        let poll_start = poll.start.content.poll_start();
+
+        let results = compile_unstable_poll_results(
+            poll_start,
+            poll.responses.iter().map(|r| r.data()),
+            poll.end.as_ref().map(|e| e.origin_server_ts),
+        );
+
+        let answers = poll_start
+            .answers
+            .iter()
+            .map(|answer| {
+                let voters: Vec<_> = results
+                    .get(answer.id.as_str())
+                    .map(|users| users.iter().map(|u| (*u).to_owned()).collect())
+                    .unwrap_or_default();
+                outputs::PollAnswer {
+                    id: answer.id.clone(),
+                    text: answer.text.clone(),
+                    count: voters.len(),
+                    voters,
+                }
+            })
+            .collect();
+
+        Ok(outputs::PollResults {
+            event_id: event_id.to_owned(),
+            question: poll_start.question.text.clone(),
+            kind: poll_start.kind.to_string(),
+            max_selections: poll_start.max_selections,
+            ended: poll.end.is_some(),
+            answers,
+        })
+    }
+}
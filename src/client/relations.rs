@@ -0,0 +1,345 @@
+use anyhow::bail;
+use matrix_sdk::room::Room;
+use matrix_sdk::ruma::api::client::relations::get_relating_events_with_rel_type;
+use matrix_sdk::ruma::api::client::threads::get_threads;
+use matrix_sdk::ruma::events::reaction::{OriginalSyncReactionEvent, ReactionEventContent};
+use matrix_sdk::ruma::events::relation::InReplyTo;
+use matrix_sdk::ruma::events::relation::{Annotation, RelationType, Thread};
+use matrix_sdk::ruma::events::room::message::{
+    AddMentions, ForwardThread, Relation, RoomMessageEventContent,
+    RoomMessageEventContentWithoutRelation,
+};
+use matrix_sdk::ruma::events::{AnyMessageLikeEvent, AnyTimelineEvent, MessageLikeEvent};
+use matrix_sdk::ruma::serde::Raw;
+use matrix_sdk::ruma::{EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, RoomId, UInt};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::warn;
+
+use super::room::MessageRelation;
+use crate::outputs;
+
+/// The parts of a thread root, which are listed by `mn threads`.
+#[derive(Deserialize)]
+struct ThreadRoot {
+    event_id: OwnedEventId,
+    sender: String,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    #[serde(default)]
+    content: EventContent,
+    #[serde(default)]
+    unsigned: ThreadRootUnsigned,
+}
+
+#[derive(Default, Deserialize)]
+struct EventContent {
+    body: Option<String>,
+    #[serde(rename = "m.relates_to")]
+    relates_to: Option<RelatesTo>,
+}
+
+#[derive(Deserialize)]
+struct RelatesTo {
+    rel_type: Option<String>,
+    event_id: Option<OwnedEventId>,
+}
+
+#[derive(Default, Deserialize)]
+struct ThreadRootUnsigned {
+    #[serde(rename = "m.relations", default)]
+    relations: ThreadRootRelations,
+}
+
+#[derive(Default, Deserialize)]
+struct ThreadRootRelations {
+    #[serde(rename = "m.thread")]
+    thread: Option<ThreadSummary>,
+}
+
+#[derive(Deserialize)]
+struct ThreadSummary {
+    count: UInt,
+    latest_event: Option<LatestEvent>,
+}
+
+#[derive(Deserialize)]
+struct LatestEvent {
+    event_id: OwnedEventId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+}
+
+impl super::Client {
+    /// Builds the relation for posting into the thread of `root`. The
+    /// reply fallback points to the latest event of the thread.
+    async fn thread_relation(
+        &self,
+        room: &Room,
+        root: &EventId,
+    ) -> anyhow::Result<Relation<RoomMessageEventContentWithoutRelation>> {
+        let root_event = room.event(root).await?;
+        let rel_type = root_event
+            .event
+            .get_field::<EventContent>("content")?
+            .and_then(|c| c.relates_to)
+            .and_then(|r| r.rel_type);
+        if let Some(rel_type) = rel_type {
+            bail!(
+                "event {} has a {} relation and cannot be a thread root",
+                root,
+                rel_type
+            );
+        }
+
+        let mut request = get_relating_events_with_rel_type::v1::Request::new(
+            room.room_id().to_owned(),
+            root.to_owned(),
+            RelationType::Thread,
+        );
+        request.limit = Some(UInt::from(1u32));
+
+        let resp = self.inner.send(request, None).await?;
+        let latest = match resp.chunk.first() {
+            Some(event) => event.get_field::<OwnedEventId>("event_id")?,
+            None => None,
+        };
+
+        Ok(Relation::Thread(Thread::plain(
+            root.to_owned(),
+            latest.unwrap_or_else(|| root.to_owned()),
+        )))
+    }
+
+    /// Turns `content` into a reply. Messages are quoted in a rich reply
+    /// fallback; other events, e.g. stickers or undecryptable messages,
+    /// are referenced without a fallback.
+    async fn make_reply(
+        &self,
+        room: &Room,
+        mut content: RoomMessageEventContent,
+        event_id: &EventId,
+    ) -> anyhow::Result<RoomMessageEventContent> {
+        let raw = room.event(event_id).await?.event;
+        let event = match raw.deserialize()? {
+            AnyTimelineEvent::MessageLike(event) => event,
+            AnyTimelineEvent::State(event) => bail!(
+                "event {} is a {} state event and cannot be replied to",
+                event_id,
+                event.event_type()
+            ),
+        };
+
+        if let AnyMessageLikeEvent::RoomMessage(MessageLikeEvent::Original(original)) = &event {
+            let add_mentions = if original.sender == self.user_id {
+                AddMentions::No
+            } else {
+                AddMentions::Yes
+            };
+            return Ok(content.make_reply_to(original, ForwardThread::Yes, add_mentions));
+        }
+        if event.original_content().is_none() {
+            bail!("event {} is redacted", event_id);
+        }
+
+        let thread_root = raw
+            .get_field::<EventContent>("content")?
+            .and_then(|c| c.relates_to)
+            .filter(|r| r.rel_type.as_deref() == Some("m.thread"))
+            .and_then(|r| r.event_id);
+
+        content.relates_to = Some(match thread_root {
+            Some(root) => Relation::Thread(Thread::reply(root, event_id.to_owned())),
+            None => Relation::Reply {
+                in_reply_to: InReplyTo::new(event_id.to_owned()),
+            },
+        });
+        Ok(content)
+    }
+
+    pub(super) async fn relate_content(
+        &self,
+        room: &Room,
+        mut content: RoomMessageEventContent,
+        relation: Option<MessageRelation<'_>>,
+    ) -> anyhow::Result<RoomMessageEventContent> {
+        match relation {
+            Some(MessageRelation::Reply(event_id)) => {
+                self.make_reply(room, content, event_id).await
+            }
+            Some(MessageRelation::Thread(root)) => {
+                content.relates_to = Some(self.thread_relation(room, root).await?);
+                Ok(content)
+            }
+            None => Ok(content),
+        }
+    }
+
+    /// Lists the thread roots of a room, most recently active first.
+    pub(crate) async fn threads(
+        &self,
+        room_id: impl AsRef<RoomId>,
+    ) -> anyhow::Result<Vec<outputs::Thread>> {
+        let room = self.get_joined_room(room_id)?;
+        let mut threads = Vec::new();
+        let mut from = None;
+
+        loop {
+            let mut request = get_threads::v1::Request::new(room.room_id().to_owned());
+            request.from = from;
+
+            let resp = self.inner.send(request, None).await?;
+            for raw in resp.chunk {
+                let root = match Raw::deserialize_as::<ThreadRoot>(&raw) {
+                    Ok(root) => root,
+                    Err(e) => {
+                        warn!("invalid thread root: {}", e);
+                        continue;
+                    }
+                };
+                let summary = root.unsigned.relations.thread;
+                let latest = summary.as_ref().and_then(|s| s.latest_event.as_ref());
+
+                threads.push(outputs::Thread {
+                    event_id: root.event_id,
+                    sender: root.sender,
+                    origin_server_ts: root.origin_server_ts,
+                    body: root.content.body,
+                    count: summary.as_ref().map(|s| s.count).unwrap_or_default(),
+                    latest_event_id: latest.map(|e| e.event_id.clone()),
+                    latest_activity: latest.map(|e| e.origin_server_ts),
+                });
+            }
+
+            match resp.next_batch {
+                Some(next_batch) => from = Some(next_batch),
+                None => break,
+            }
+        }
+
+        Ok(threads)
+    }
+
+    /// Fetches all events with a `rel_type` relation to an event and
+    /// returns those of `event_type`. Encrypted events are decrypted.
+    pub(super) async fn related_events<T>(
+        &self,
+        room: &Room,
+        event_id: &EventId,
+        rel_type: RelationType,
+        event_type: &str,
+    ) -> anyhow::Result<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut events = Vec::new();
+        let mut from = None;
+
+        loop {
+            let mut request = get_relating_events_with_rel_type::v1::Request::new(
+                room.room_id().to_owned(),
+                event_id.to_owned(),
+                rel_type.clone(),
+            );
+            request.from = from;
+
+            let resp = self.inner.send(request, None).await?;
+            for raw in resp.chunk {
+                let raw: Raw<AnyTimelineEvent> = match raw.get_field::<String>("type")?.as_deref() {
+                    Some("m.room.encrypted") => match room.decrypt_event(raw.cast_ref()).await {
+                        Ok(event) => event.event,
+                        Err(e) => {
+                            warn!("could not decrypt related event: {}", e);
+                            continue;
+                        }
+                    },
+                    _ => raw.cast(),
+                };
+                if raw.get_field::<String>("type")?.as_deref() != Some(event_type) {
+                    continue;
+                }
+                match raw.deserialize_as() {
+                    Ok(event) => events.push(event),
+                    Err(e) => warn!("invalid {} event: {}", event_type, e),
+                }
+            }
+
+            match resp.next_batch {
+                Some(next_batch) => from = Some(next_batch),
+                None => break,
+            }
+        }
+
+        Ok(events)
+    }
+
+    async fn annotations(
+        &self,
+        room: &Room,
+        event_id: &EventId,
+    ) -> anyhow::Result<Vec<OriginalSyncReactionEvent>> {
+        self.related_events(room, event_id, RelationType::Annotation, "m.reaction")
+            .await
+    }
+
+    pub(crate) async fn send_reaction(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_id: &EventId,
+        key: &str,
+    ) -> anyhow::Result<OwnedEventId> {
+        let room = self.get_joined_room(room_id)?;
+        let content =
+            ReactionEventContent::new(Annotation::new(event_id.to_owned(), key.to_owned()));
+        let resp = room.send(content).await?;
+        Ok(resp.event_id)
+    }
+
+    /// Redacts the own reaction with `key`; returns the ID of the redaction.
+    pub(crate) async fn remove_reaction(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_id: &EventId,
+        key: &str,
+    ) -> anyhow::Result<OwnedEventId> {
+        let room = self.get_joined_room(room_id)?;
+        let reaction = self
+            .annotations(&room, event_id)
+            .await?
+            .into_iter()
+            .find(|r| r.sender == self.user_id && r.content.relates_to.key == key);
+
+        let Some(reaction) = reaction else {
+            bail!("no reaction {} to event {}", key, event_id);
+        };
+        let resp = room.redact(&reaction.event_id, None, None).await?;
+        Ok(resp.event_id)
+    }
+
+    /// Lists the reactions to an event grouped by their key.
+    pub(crate) async fn reactions(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_id: &EventId,
+    ) -> anyhow::Result<Vec<outputs::Reaction>> {
+        let room = self.get_joined_room(room_id)?;
+        let mut out: Vec<outputs::Reaction> = Vec::new();
+
+        for reaction in self.annotations(&room, event_id).await? {
+            let key = reaction.content.relates_to.key;
+            let entry = match out.iter_mut().find(|r| r.key == key) {
+                Some(entry) => entry,
+                None => {
+                    out.push(outputs::Reaction {
+                        key,
+                        count: 0,
+                        senders: Vec::new(),
+                    });
+                    out.last_mut().unwrap()
+                }
+            };
+            entry.count += 1;
+            entry.senders.push(reaction.sender);
+        }
+
+        Ok(out)
+    }
+}
@@ -0,0 +1,185 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+use matrix_sdk::ruma::matrix_uri::MatrixId;
+use matrix_sdk::ruma::{
+    MatrixToUri, MatrixUri, OwnedRoomAliasId, OwnedRoomId, OwnedRoomOrAliasId, OwnedServerName,
+    RoomAliasId, RoomId,
+};
+use matrix_sdk::RoomState;
+
+/// A room given on the command line as room ID, alias, `matrix:` URI,
+/// matrix.to link or display name.
+#[derive(Clone, Debug)]
+pub(crate) enum RoomRef {
+    Id(OwnedRoomId, Vec<OwnedServerName>),
+    Alias(OwnedRoomAliasId, Vec<OwnedServerName>),
+    Name(String),
+}
+
+impl FromStr for RoomRef {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, via) = if s.starts_with("https://matrix.to/") {
+            let uri = MatrixToUri::parse(s)?;
+            (uri.id().clone(), uri.via().to_vec())
+        } else if s.starts_with("matrix:") {
+            let uri = MatrixUri::parse(s)?;
+            (uri.id().clone(), uri.via().to_vec())
+        } else if s.starts_with('!') {
+            return Ok(Self::Id(RoomId::parse(s)?, Vec::new()));
+        } else if s.starts_with('#') {
+            return Ok(Self::Alias(RoomAliasId::parse(s)?, Vec::new()));
+        } else if s.trim().is_empty() {
+            bail!("empty room name");
+        } else {
+            return Ok(Self::Name(s.to_owned()));
+        };
+
+        match id {
+            MatrixId::Room(room_id) => Ok(Self::Id(room_id, via)),
+            MatrixId::RoomAlias(alias) => Ok(Self::Alias(alias, via)),
+            MatrixId::Event(room, _) => match OwnedRoomId::try_from(room) {
+                Ok(room_id) => Ok(Self::Id(room_id, via)),
+                Err(alias) => Ok(Self::Alias(alias, via)),
+            },
+            _ => bail!("{} does not point to a room", s),
+        }
+    }
+}
+
+impl RoomRef {
+    /// The room ID or alias and the servers to join via; names have to
+    /// be resolved first.
+    pub(crate) fn to_room_or_alias_id(&self) -> Option<(OwnedRoomOrAliasId, Vec<OwnedServerName>)> {
+        match self {
+            Self::Id(room_id, via) => Some((room_id.clone().into(), via.clone())),
+            Self::Alias(alias, via) => Some((alias.clone().into(), via.clone())),
+            Self::Name(_) => None,
+        }
+    }
+}
+
+impl super::Client {
+    /// Looks up the room ID. Aliases are resolved via the room directory,
+    /// names by the rooms in the local store.
+    pub(crate) async fn resolve_room(&self, room: &RoomRef) -> anyhow::Result<OwnedRoomId> {
+        match room {
+            RoomRef::Id(room_id, _) => Ok(room_id.clone()),
+            RoomRef::Alias(alias, _) => Ok(self.inner.resolve_room_alias(alias).await?.room_id),
+            RoomRef::Name(name) => self.find_room_by_name(name).await,
+        }
+    }
+
+    /// Finds the single room with this name or display name. Left rooms
+    /// are only considered, if no other room matches.
+    async fn find_room_by_name(&self, name: &str) -> anyhow::Result<OwnedRoomId> {
+        let mut found = Vec::new();
+        let mut left = Vec::new();
+
+        for room in self.inner.rooms() {
+            let matches = room.name().as_deref() == Some(name)
+                || room.display_name().await?.to_string() == name;
+            if !matches {
+                continue;
+            }
+            if room.state() == RoomState::Left {
+                left.push(room.room_id().to_owned());
+            } else {
+                found.push(room.room_id().to_owned());
+            }
+        }
+        if found.is_empty() {
+            found = left;
+        }
+
+        match &found[..] {
+            [] => bail!("no room named {:?}", name),
+            [room_id] => Ok(room_id.clone()),
+            _ => bail!(
+                "room name {:?} is ambiguous: {}",
+                name,
+                found
+                    .iter()
+                    .map(|r| r.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> RoomRef {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn room_ids_and_aliases() {
+        assert!(matches!(parse("!abc:example.org"), RoomRef::Id(id, via)
+            if id == "!abc:example.org" && via.is_empty()));
+        assert!(
+            matches!(parse("#ops:example.org"), RoomRef::Alias(alias, via)
+            if alias == "#ops:example.org" && via.is_empty())
+        );
+        assert!("#ops".parse::<RoomRef>().is_err());
+    }
+
+    #[test]
+    fn matrix_to_links() {
+        assert!(matches!(
+            parse("https://matrix.to/#/!abc:example.org?via=example.com"),
+            RoomRef::Id(id, via) if id == "!abc:example.org" && via == ["example.com"]
+        ));
+        assert!(matches!(
+            parse("https://matrix.to/#/%23ops:example.org"),
+            RoomRef::Alias(alias, _) if alias == "#ops:example.org"
+        ));
+        assert!(matches!(
+            parse("https://matrix.to/#/!abc:example.org/$event:example.org"),
+            RoomRef::Id(id, _) if id == "!abc:example.org"
+        ));
+        assert!("https://matrix.to/#/@alice:example.org"
+            .parse::<RoomRef>()
+            .is_err());
+    }
+
+    #[test]
+    fn matrix_uris() {
+        assert!(matches!(
+            parse("matrix:roomid/abc:example.org?via=example.com"),
+            RoomRef::Id(id, via) if id == "!abc:example.org" && via == ["example.com"]
+        ));
+        assert!(matches!(
+            parse("matrix:r/ops:example.org"),
+            RoomRef::Alias(alias, _) if alias == "#ops:example.org"
+        ));
+        assert!(matches!(
+            parse("matrix:r/ops:example.org/e/event"),
+            RoomRef::Alias(alias, _) if alias == "#ops:example.org"
+        ));
+        assert!("matrix:u/alice:example.org".parse::<RoomRef>().is_err());
+    }
+
+    #[test]
+    fn names() {
+        assert!(matches!(parse("Alerts"), RoomRef::Name(name) if name == "Alerts"));
+        assert!(matches!(parse("ops room"), RoomRef::Name(name) if name == "ops room"));
+        assert!("".parse::<RoomRef>().is_err());
+        assert!("  ".parse::<RoomRef>().is_err());
+    }
+
+    #[test]
+    fn join_targets() {
+        assert!(parse("Alerts").to_room_or_alias_id().is_none());
+        let (target, via) = parse("https://matrix.to/#/%23ops:example.org?via=example.com")
+            .to_room_or_alias_id()
+            .unwrap();
+        assert_eq!(target, "#ops:example.org");
+        assert_eq!(via, ["example.com"]);
+    }
+}
@@ -1,17 +1,178 @@
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, bail};
-use matrix_sdk::attachment::AttachmentConfig;
-use matrix_sdk::room::{self, Messages, MessagesOptions, Room};
+use clap::ValueEnum;
+use matrix_sdk::deserialized_responses::TimelineEvent;
+use matrix_sdk::room::{self, MessagesOptions, Room};
+use matrix_sdk::ruma::api::client::context::get_context;
+use matrix_sdk::ruma::api::client::filter::RoomEventFilter;
+use matrix_sdk::ruma::api::client::room::get_event_by_timestamp;
+use matrix_sdk::ruma::api::Direction;
+use matrix_sdk::ruma::events::room::message::RoomMessageEvent;
 use matrix_sdk::ruma::events::room::message::{
-    AddMentions, EmoteMessageEventContent, MessageType, RoomMessageEventContent,
+    AudioInfo, AudioMessageEventContent, EmoteMessageEventContent, FileInfo,
+    FileMessageEventContent, ImageMessageEventContent, MessageType, NoticeMessageEventContent,
+    Relation, RoomMessageEventContent, TextMessageEventContent, UnstableAudioDetailsContentBlock,
+    UnstableVoiceContentBlock, VideoInfo, VideoMessageEventContent,
 };
-use matrix_sdk::ruma::events::room::message::{ForwardThread, RoomMessageEvent};
-use matrix_sdk::ruma::RoomId;
-use matrix_sdk::ruma::{OwnedEventId, OwnedMxcUri};
+use matrix_sdk::ruma::events::room::{ImageInfo, MediaSource, ThumbnailInfo};
+use matrix_sdk::ruma::{EventId, MxcUri, OwnedEventId, OwnedUserId};
+use matrix_sdk::ruma::{MilliSecondsSinceUnixEpoch, RoomId, UInt};
 use matrix_sdk::RoomMemberships;
 
+use super::media::Thumbnail;
+use super::mentions::{add_mentions, MentionOptions, Pills};
+use crate::attachment::{
+    attachment_metadata, recompress_image, voice_details, Attachment, AttachmentOptions, Caption,
+};
+use crate::util::sanitize_filename;
+
+pub(super) const MESSAGES_PAGE_SIZE: u64 = 100;
+
+/// Parameters for paginating through the timeline of a room.
+#[derive(Debug, Default)]
+pub(crate) struct MessagesQuery {
+    pub(crate) from: Option<String>,
+    pub(crate) to: Option<String>,
+    pub(crate) forward: bool,
+    pub(crate) since: Option<MilliSecondsSinceUnixEpoch>,
+    pub(crate) until: Option<MilliSecondsSinceUnixEpoch>,
+    pub(crate) types: Vec<String>,
+    pub(crate) senders: Vec<OwnedUserId>,
+    /// Maximum number of events; 0 means no limit.
+    pub(crate) limit: u64,
+}
+
+/// An attachment ready for sending to one or more rooms. It is uploaded
+/// at most once for encrypted and once for unencrypted rooms.
+pub(crate) struct PreparedAttachment {
+    file_name: String,
+    content_type: mime::Mime,
+    data: Vec<u8>,
+    /// The unmodified image, which is sent as file before the recompressed one.
+    original: Option<(String, mime::Mime, Vec<u8>)>,
+    voice: Option<UnstableAudioDetailsContentBlock>,
+    uploads: HashMap<bool, UploadedAttachment>,
+}
+
+#[derive(Clone)]
+struct UploadedAttachment {
+    original: Option<MessageType>,
+    msgtype: MessageType,
+}
+
+impl PreparedAttachment {
+    pub(crate) fn new(attachment: Attachment, options: &AttachmentOptions) -> anyhow::Result<Self> {
+        let Attachment { file_name, data } = attachment;
+        let content_type = match &options.mime {
+            Some(content_type) => content_type.clone(),
+            None => crate::mime::guess_mime_data(&file_name, &data),
+        };
+        let voice = match options.voice {
+            true => Some(voice_details(&content_type, &data)?),
+            false => None,
+        };
+
+        let Some((new_data, new_content_type)) =
+            recompress_image(&content_type, &data, &options.image)?
+        else {
+            return Ok(Self {
+                file_name,
+                content_type,
+                data,
+                original: None,
+                voice,
+                uploads: HashMap::new(),
+            });
+        };
+
+        let new_path = match new_content_type.subtype().as_str() {
+            "jpeg" => Path::new(&file_name).with_extension("jpg"),
+            ext => Path::new(&file_name).with_extension(ext),
+        };
+        let new_file_name = new_path.to_string_lossy().into_owned();
+        let original = match options.keep_original {
+            true => Some((file_name, content_type, data)),
+            false => None,
+        };
+
+        Ok(Self {
+            file_name: new_file_name,
+            content_type: new_content_type,
+            data: new_data,
+            original,
+            voice,
+            uploads: HashMap::new(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum AvatarFormat {
+    /// The plain mxc:// URI
+    Mxc,
+    /// A thumbnail URL of the authenticated media API
+    Url,
+    /// Download the thumbnail and output its path
+    Download,
+}
+
+#[derive(Debug)]
+pub(crate) struct AvatarOptions {
+    pub(crate) format: AvatarFormat,
+    pub(crate) thumbnail: Thumbnail,
+    /// Target directory for downloaded avatars.
+    pub(crate) dir: PathBuf,
+}
+
+/// How an outgoing message relates to an existing event.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum MessageRelation<'a> {
+    /// Reply to the event.
+    Reply(&'a EventId),
+    /// Post into the thread of the event.
+    Thread(&'a EventId),
+}
+
+/// The events collected over several `/messages` requests.
+pub(crate) struct MessagesPage {
+    pub(crate) start: Option<String>,
+    pub(crate) end: Option<String>,
+    pub(crate) chunk: Vec<TimelineEvent>,
+}
+
+/// Media captions are not supported by ruma yet; the caption replaces the
+/// body and the file name moves to the `filename` field.
+fn content_with_caption(
+    content: &RoomMessageEventContent,
+    file_name: &str,
+    caption: &Caption,
+    pills: &Pills,
+) -> anyhow::Result<serde_json::Value> {
+    let mut text = if caption.markdown {
+        TextMessageEventContent::markdown(&caption.body)
+    } else {
+        TextMessageEventContent::plain(&caption.body)
+    };
+
+    pills.apply(&mut text.body, &mut text.formatted);
+
+    let mut value = serde_json::to_value(content)?;
+    let Some(object) = value.as_object_mut() else {
+        bail!("bug? content is not an object");
+    };
+    object.insert("filename".to_owned(), file_name.into());
+    object.insert("body".to_owned(), text.body.into());
+    if let Some(formatted) = text.formatted {
+        object.insert("format".to_owned(), formatted.format.as_str().into());
+        object.insert("formatted_body".to_owned(), formatted.body.into());
+    }
+
+    Ok(value)
+}
+
 impl super::Client {
     pub(crate) fn get_joined_room(
         &self,
@@ -26,10 +187,40 @@ impl super::Client {
         &self,
         room_id: impl AsRef<RoomId>,
         content: RoomMessageEventContent,
-    ) -> anyhow::Result<()> {
+        relation: Option<MessageRelation<'_>>,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<OwnedEventId> {
+        let room = self.get_joined_room(room_id)?;
+        let content = self.mention(&room, content, mentions).await?;
+        let content = self.relate_content(&room, content, relation).await?;
+        let resp = room.send(content).await?;
+        Ok(resp.event_id)
+    }
+
+    /// Sends a message-like event of any type.
+    pub(crate) async fn send_custom_event(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_type: &str,
+        content: serde_json::Value,
+    ) -> anyhow::Result<OwnedEventId> {
+        let room = self.get_joined_room(room_id)?;
+        let resp = room.send_raw(event_type, content).await?;
+        Ok(resp.event_id)
+    }
+
+    pub(crate) async fn send_state_event(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_type: &str,
+        state_key: &str,
+        content: serde_json::Value,
+    ) -> anyhow::Result<OwnedEventId> {
         let room = self.get_joined_room(room_id)?;
-        room.send(content).await?;
-        Ok(())
+        let resp = room
+            .send_state_event_raw(event_type, state_key, content)
+            .await?;
+        Ok(resp.event_id)
     }
 
     pub(crate) async fn send_message(
@@ -37,35 +228,65 @@ impl super::Client {
         room: impl AsRef<RoomId>,
         body: &str,
         markdown: bool,
-    ) -> anyhow::Result<()> {
+        relation: Option<MessageRelation<'_>>,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<OwnedEventId> {
         let content = if markdown {
             RoomMessageEventContent::text_markdown(body)
         } else {
             RoomMessageEventContent::text_plain(body)
         };
-        self.send_message_raw(room, content).await
+        self.send_message_raw(room, content, relation, mentions)
+            .await
     }
 
-    pub(crate) async fn send_message_reply(
+    /// Replaces the content of a previously sent message. Notices and
+    /// emotes keep their message type.
+    pub(crate) async fn edit_message(
         &self,
         room_id: impl AsRef<RoomId>,
-        event_id: &OwnedEventId,
+        event_id: &EventId,
         body: &str,
         markdown: bool,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<OwnedEventId> {
         let room = self.get_joined_room(&room_id)?;
         let timeline_event = room.event(event_id).await?;
-        let event_content = timeline_event.event.deserialize_as::<RoomMessageEvent>()?;
-        let original_message = event_content.as_original().unwrap();
+        let event = timeline_event.event.deserialize_as::<RoomMessageEvent>()?;
 
-        let content = if markdown {
-            RoomMessageEventContent::text_markdown(body)
-        } else {
-            RoomMessageEventContent::text_plain(body)
+        let Some(original) = event.as_original() else {
+            bail!("event {} is redacted", event_id);
+        };
+        if original.sender != self.user_id {
+            bail!("event {} was sent by {}", event_id, original.sender);
         }
-        .make_reply_to(original_message, ForwardThread::Yes, AddMentions::No);
+        if let Some(Relation::Replacement(replacement)) = &original.content.relates_to {
+            bail!(
+                "event {} is an edit; edit {} instead",
+                event_id,
+                replacement.event_id
+            );
+        }
+
+        let msgtype = match (&original.content.msgtype, markdown) {
+            (MessageType::Notice(_), true) => {
+                MessageType::Notice(NoticeMessageEventContent::markdown(body))
+            }
+            (MessageType::Notice(_), false) => {
+                MessageType::Notice(NoticeMessageEventContent::plain(body))
+            }
+            (MessageType::Emote(_), true) => {
+                MessageType::Emote(EmoteMessageEventContent::markdown(body))
+            }
+            (MessageType::Emote(_), false) => {
+                MessageType::Emote(EmoteMessageEventContent::plain(body))
+            }
+            (_, true) => MessageType::Text(TextMessageEventContent::markdown(body)),
+            (_, false) => MessageType::Text(TextMessageEventContent::plain(body)),
+        };
+        let content = RoomMessageEventContent::new(msgtype).make_replacement(original, None);
 
-        self.send_message_raw(room_id, content).await
+        self.send_message_raw(room_id, content, None, &MentionOptions::default())
+            .await
     }
 
     pub(crate) async fn send_notice(
@@ -73,13 +294,16 @@ impl super::Client {
         room_id: impl AsRef<RoomId>,
         body: &str,
         markdown: bool,
-    ) -> anyhow::Result<()> {
+        relation: Option<MessageRelation<'_>>,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<OwnedEventId> {
         let event = if markdown {
             RoomMessageEventContent::notice_markdown(body)
         } else {
             RoomMessageEventContent::notice_plain(body)
         };
-        self.send_message_raw(room_id, event).await
+        self.send_message_raw(room_id, event, relation, mentions)
+            .await
     }
 
     pub(crate) async fn send_emote(
@@ -87,7 +311,9 @@ impl super::Client {
         room_id: impl AsRef<RoomId>,
         body: &str,
         markdown: bool,
-    ) -> anyhow::Result<()> {
+        relation: Option<MessageRelation<'_>>,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<OwnedEventId> {
         let content = if markdown {
             EmoteMessageEventContent::markdown(body)
         } else {
@@ -95,46 +321,205 @@ impl super::Client {
         };
         let msgtype = MessageType::Emote(content);
         let content = RoomMessageEventContent::new(msgtype);
-        self.send_message_raw(room_id, content).await
+        self.send_message_raw(room_id, content, relation, mentions)
+            .await
     }
 
-    pub(crate) async fn send_attachment(
+    async fn upload_source(
         &self,
-        room_id: impl AsRef<RoomId>,
-        path: impl AsRef<Path>,
-    ) -> anyhow::Result<()> {
-        let path = path.as_ref();
-        let Some(file_name) = path.file_name().map(|s| s.to_str().unwrap()) else {
-            bail!("invalid file: {:?}", path);
+        content_type: &mime::Mime,
+        data: Vec<u8>,
+        encrypt: bool,
+    ) -> anyhow::Result<MediaSource> {
+        let upload = self.upload_media(content_type, data, encrypt).await?;
+        Ok(match upload.encryption {
+            Some(file) => MediaSource::Encrypted(Box::new(file)),
+            None => MediaSource::Plain(upload.mxc_uri),
+        })
+    }
+
+    /// Uploads an attachment including its thumbnail and builds the
+    /// matching message type. With `as_file` it always becomes `m.file`.
+    async fn prepare_attachment(
+        &self,
+        encrypt: bool,
+        file_name: &str,
+        content_type: &mime::Mime,
+        data: &[u8],
+        as_file: bool,
+    ) -> anyhow::Result<MessageType> {
+        let body = file_name.to_owned();
+        let mimetype = Some(content_type.essence_str().to_owned());
+
+        if as_file {
+            let mut info = FileInfo::new();
+            info.mimetype = mimetype;
+            info.size = UInt::new(data.len() as u64);
+            let source = self
+                .upload_source(content_type, data.to_vec(), encrypt)
+                .await?;
+            let content = FileMessageEventContent::new(body.clone(), source)
+                .filename(body)
+                .info(Box::new(info));
+            return Ok(MessageType::File(content));
+        }
+
+        let (info, thumbnail) = attachment_metadata(content_type, data);
+        let (thumbnail_source, thumbnail_info) = match thumbnail {
+            Some(thumbnail) => {
+                let mut info = thumbnail.info.map(ThumbnailInfo::from).unwrap_or_default();
+                info.mimetype = Some(thumbnail.content_type.essence_str().to_owned());
+                let source = self
+                    .upload_source(&thumbnail.content_type, thumbnail.data, encrypt)
+                    .await?;
+                (Some(source), Some(Box::new(info)))
+            }
+            None => (None, None),
         };
+        let source = self
+            .upload_source(content_type, data.to_vec(), encrypt)
+            .await?;
 
-        let room = self.get_joined_room(room_id)?;
-        let data = fs::read(path)?;
-        let config = AttachmentConfig::default().generate_thumbnail(None);
-        let content_type = crate::mime::guess_mime(path)?;
+        let msgtype = match content_type.type_() {
+            mime::IMAGE => {
+                let mut info = ImageInfo::from(info);
+                info.mimetype = mimetype;
+                info.thumbnail_source = thumbnail_source;
+                info.thumbnail_info = thumbnail_info;
+                MessageType::Image(ImageMessageEventContent::new(body, source).info(Box::new(info)))
+            }
+            mime::AUDIO => {
+                let mut info = AudioInfo::from(info);
+                info.mimetype = mimetype;
+                MessageType::Audio(AudioMessageEventContent::new(body, source).info(Box::new(info)))
+            }
+            mime::VIDEO => {
+                let mut info = VideoInfo::from(info);
+                info.mimetype = mimetype;
+                info.thumbnail_source = thumbnail_source;
+                info.thumbnail_info = thumbnail_info;
+                MessageType::Video(VideoMessageEventContent::new(body, source).info(Box::new(info)))
+            }
+            _ => {
+                let mut info = FileInfo::from(info);
+                info.mimetype = mimetype;
+                let content = FileMessageEventContent::new(body.clone(), source)
+                    .filename(body)
+                    .info(Box::new(info));
+                MessageType::File(content)
+            }
+        };
 
-        room.send_attachment(file_name, &content_type, data, config)
+        Ok(msgtype)
+    }
+
+    /// Uploads the attachment, unless it has been uploaded with the same
+    /// encryption setting before.
+    async fn upload_attachment(
+        &self,
+        attachment: &mut PreparedAttachment,
+        encrypt: bool,
+    ) -> anyhow::Result<UploadedAttachment> {
+        if let Some(uploaded) = attachment.uploads.get(&encrypt) {
+            return Ok(uploaded.clone());
+        }
+
+        let original = match &attachment.original {
+            Some((file_name, content_type, data)) => Some(
+                self.prepare_attachment(encrypt, file_name, content_type, data, true)
+                    .await?,
+            ),
+            None => None,
+        };
+        let mut msgtype = self
+            .prepare_attachment(
+                encrypt,
+                &attachment.file_name,
+                &attachment.content_type,
+                &attachment.data,
+                false,
+            )
             .await?;
-        Ok(())
+        if let (Some(audio), MessageType::Audio(content)) = (&attachment.voice, &mut msgtype) {
+            content.audio = Some(audio.clone());
+            content.voice = Some(UnstableVoiceContentBlock::new());
+        }
+
+        let uploaded = UploadedAttachment { original, msgtype };
+        attachment.uploads.insert(encrypt, uploaded.clone());
+        Ok(uploaded)
+    }
+
+    pub(crate) async fn send_attachment(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        attachment: &mut PreparedAttachment,
+        caption: Option<&Caption>,
+        relation: Option<MessageRelation<'_>>,
+        mentions: &MentionOptions,
+    ) -> anyhow::Result<OwnedEventId> {
+        let room = self.get_joined_room(room_id)?;
+        let encrypt = room.is_encrypted().await?;
+        let uploaded = self.upload_attachment(attachment, encrypt).await?;
+
+        if let Some(original) = uploaded.original {
+            let content = self
+                .relate_content(&room, RoomMessageEventContent::new(original), relation)
+                .await?;
+            room.send(content).await?;
+        }
+
+        let pills = self.pills(&room, mentions).await?;
+        let content = add_mentions(
+            RoomMessageEventContent::new(uploaded.msgtype),
+            mentions,
+            &pills,
+        );
+        let content = self.relate_content(&room, content, relation).await?;
+
+        let resp = match caption {
+            Some(caption) => {
+                let content =
+                    content_with_caption(&content, &attachment.file_name, caption, &pills)?;
+                room.send_raw("m.room.message", content).await?
+            }
+            None => room.send(content).await?,
+        };
+        Ok(resp.event_id)
     }
 
-    pub(crate) fn mxc_to_http(&self, mxc: OwnedMxcUri) -> String {
+    async fn avatar(&self, mxc: &MxcUri, options: &AvatarOptions) -> anyhow::Result<String> {
         if !mxc.is_valid() {
-            return String::from("");
+            return Ok(String::from(""));
+        }
+
+        match options.format {
+            AvatarFormat::Mxc => Ok(mxc.to_string()),
+            AvatarFormat::Url => self.media_url(mxc, Some(&options.thumbnail)),
+            AvatarFormat::Download => {
+                let (server_name, media_id) = mxc.parts()?;
+                let path = options
+                    .dir
+                    .join(sanitize_filename(&format!("{}_{}", server_name, media_id)));
+                if !path.try_exists()? {
+                    let resp = self.fetch_media(mxc, Some(&options.thumbnail)).await?;
+                    fs::create_dir_all(&options.dir)?;
+                    fs::write(&path, resp.data)?;
+                }
+                Ok(path.to_string_lossy().into_owned())
+            }
         }
-        format!(
-            "{}_matrix/media/v3/thumbnail/{}/{}?width=50&height=50&method=scale",
-            self.inner.homeserver().as_str(),
-            mxc.server_name().unwrap(),
-            mxc.media_id().unwrap(),
-        )
     }
 
-    pub(crate) async fn query_room(&self, room: Room) -> anyhow::Result<crate::outputs::Room> {
+    pub(crate) async fn query_room(
+        &self,
+        room: Room,
+        avatar_options: &AvatarOptions,
+    ) -> anyhow::Result<crate::outputs::Room> {
         let mut members_out = Vec::new();
         for member in room.members(RoomMemberships::empty()).await? {
             let avatar = match member.avatar_url() {
-                Some(uri) => self.mxc_to_http(OwnedMxcUri::from(uri)),
+                Some(uri) => self.avatar(uri, avatar_options).await?,
                 None => String::from(""),
             };
             members_out.push(crate::outputs::RoomMember {
@@ -161,7 +546,7 @@ impl super::Client {
             history_visibility: room.history_visibility().to_string(),
             guest_access: room.guest_access().to_string(),
             avatar: match room.avatar_url() {
-                Some(url) => self.mxc_to_http(url.clone()),
+                Some(url) => self.avatar(&url, avatar_options).await?,
                 None => String::from(""),
             },
             matrix_uri: room.matrix_permalink(false).await?.to_string(),
@@ -173,14 +558,110 @@ impl super::Client {
         Ok(room_out)
     }
 
+    async fn pagination_token_at(
+        &self,
+        room: &Room,
+        ts: MilliSecondsSinceUnixEpoch,
+        dir: Direction,
+    ) -> anyhow::Result<Option<String>> {
+        let request = get_event_by_timestamp::v1::Request::new(room.room_id().to_owned(), ts, dir);
+        let resp = self.inner.send(request, None).await?;
+
+        let mut request = get_context::v3::Request::new(room.room_id().to_owned(), resp.event_id);
+        request.limit = (0u32).into();
+        let resp = self.inner.send(request, None).await?;
+
+        // The found event must be part of the result, thus start
+        // in front of it in the direction of pagination.
+        Ok(match dir {
+            Direction::Forward => resp.start,
+            Direction::Backward => resp.end,
+        })
+    }
+
     pub(crate) async fn messages(
         &self,
         room_id: impl AsRef<RoomId>,
-        limit: u64,
-    ) -> anyhow::Result<Messages> {
+        query: MessagesQuery,
+    ) -> anyhow::Result<MessagesPage> {
         let room = self.get_joined_room(room_id)?;
-        let mut options = MessagesOptions::backward();
-        options.limit = limit.try_into()?;
-        room.messages(options).await.map_err(|e| anyhow!(e))
+        let dir = if query.forward {
+            Direction::Forward
+        } else {
+            Direction::Backward
+        };
+
+        let mut filter = RoomEventFilter::default();
+        if !query.types.is_empty() {
+            filter.types = Some(query.types.clone());
+        }
+        if !query.senders.is_empty() {
+            filter.senders = Some(query.senders.clone());
+        }
+
+        let mut from = query.from.clone();
+        if from.is_none() {
+            let anchor = match dir {
+                Direction::Forward => query.since,
+                Direction::Backward => query.until,
+            };
+            if let Some(ts) = anchor {
+                from = self.pagination_token_at(&room, ts, dir).await?;
+            }
+        }
+
+        let mut page = MessagesPage {
+            start: None,
+            end: None,
+            chunk: Vec::new(),
+        };
+
+        loop {
+            let remaining = match query.limit {
+                0 => MESSAGES_PAGE_SIZE,
+                n => (n - page.chunk.len() as u64).min(MESSAGES_PAGE_SIZE),
+            };
+
+            let mut options = MessagesOptions::new(dir).from(from.as_deref());
+            options.to = query.to.clone();
+            options.limit = remaining.try_into()?;
+            options.filter = filter.clone();
+
+            let resp = room.messages(options).await?;
+            let mut done = false;
+
+            page.start.get_or_insert(resp.start);
+            page.end = resp.end.clone();
+
+            for event in resp.chunk {
+                let ts = event
+                    .event
+                    .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")?;
+
+                if let Some(ts) = ts {
+                    if query.since.is_some_and(|since| ts < since) {
+                        done |= dir == Direction::Backward;
+                        continue;
+                    }
+                    if query.until.is_some_and(|until| ts > until) {
+                        done |= dir == Direction::Forward;
+                        continue;
+                    }
+                }
+
+                page.chunk.push(event);
+            }
+
+            if query.limit != 0 && page.chunk.len() as u64 >= query.limit {
+                done = true;
+            }
+
+            match resp.end {
+                Some(end) if !done => from = Some(end),
+                _ => break,
+            }
+        }
+
+        Ok(page)
     }
 }
@@ -0,0 +1,64 @@
+use anyhow::bail;
+use matrix_sdk::deserialized_responses::RawAnySyncOrStrippedState;
+use matrix_sdk::ruma::api::client::state::get_state_events;
+use matrix_sdk::ruma::events::StateEventType;
+use matrix_sdk::ruma::RoomId;
+use serde_json::value::RawValue;
+
+fn into_json(raw: RawAnySyncOrStrippedState) -> Box<RawValue> {
+    match raw {
+        RawAnySyncOrStrippedState::Sync(raw) => raw.into_json(),
+        RawAnySyncOrStrippedState::Stripped(raw) => raw.into_json(),
+    }
+}
+
+impl super::Client {
+    /// Fetches the current state events of a room, optionally filtered by
+    /// type and state key. With `cached`, the local store is queried
+    /// instead of the homeserver.
+    pub(crate) async fn room_state(
+        &self,
+        room_id: impl AsRef<RoomId>,
+        event_type: Option<&str>,
+        state_key: Option<&str>,
+        cached: bool,
+    ) -> anyhow::Result<Vec<Box<RawValue>>> {
+        let room = self.get_joined_room(room_id)?;
+
+        if cached {
+            let Some(event_type) = event_type else {
+                bail!("the store can only be queried for a specific type");
+            };
+            let event_type = StateEventType::from(event_type);
+            let events = match state_key {
+                Some(state_key) => room
+                    .get_state_event(event_type, state_key)
+                    .await?
+                    .into_iter()
+                    .collect(),
+                None => room.get_state_events(event_type).await?,
+            };
+            return Ok(events.into_iter().map(into_json).collect());
+        }
+
+        let request = get_state_events::v3::Request::new(room.room_id().to_owned());
+        let resp = self.inner.send(request, None).await?;
+
+        let mut events = Vec::new();
+        for raw in resp.room_state {
+            if let Some(event_type) = event_type {
+                if raw.get_field::<String>("type")?.as_deref() != Some(event_type) {
+                    continue;
+                }
+            }
+            if let Some(state_key) = state_key {
+                if raw.get_field::<String>("state_key")?.as_deref() != Some(state_key) {
+                    continue;
+                }
+            }
+            events.push(raw.into_json());
+        }
+
+        Ok(events)
+    }
+}
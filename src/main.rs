@@ -1,8 +1,10 @@
 use std::env;
+use std::fs;
+use std::io::{self, Write};
 use std::path::PathBuf;
 
 use anyhow::bail;
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
 use clap_verbosity_flag::Verbosity;
 use futures::StreamExt;
 use matrix_sdk::config::SyncSettings;
@@ -12,17 +14,31 @@ use matrix_sdk::ruma::api::client::receipt::create_receipt::v3::ReceiptType;
 use matrix_sdk::ruma::events::receipt::ReceiptThread;
 use matrix_sdk::ruma::presence::PresenceState;
 use matrix_sdk::ruma::{events::AnySyncTimelineEvent, serde::Raw};
-use matrix_sdk::ruma::{OwnedEventId, OwnedRoomId, OwnedUserId};
+use matrix_sdk::ruma::{MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri, OwnedUserId};
 use matrix_sdk::RoomState;
 use serde::Serialize;
 use serde_json::value::RawValue;
 
+mod attachment;
 mod client;
 mod mime;
 mod outputs;
 mod terminal;
 mod util;
 
+use crate::attachment::{Attachment, AttachmentOptions, Caption, ImageOptions, RecompressFormat};
+use crate::client::admin::{CreateRoomOptions, RoomPreset};
+use crate::client::broadcast::Payload;
+use crate::client::export::ExportFormat;
+use crate::client::invites::{InvitePolicy, InviteRule};
+use crate::client::location::GeoUri;
+use crate::client::media::{Thumbnail, ThumbnailMethod};
+use crate::client::mentions::MentionOptions;
+use crate::client::poll::PollOptions;
+use crate::client::resolve::RoomRef;
+use crate::client::room::{
+    AvatarFormat, AvatarOptions, MessageRelation, MessagesQuery, PreparedAttachment,
+};
 use crate::client::{session, Client};
 
 const CRATE_NAME: &str = clap::crate_name!();
@@ -49,6 +65,43 @@ struct Cli {
 enum Command {
     /// Delete session store and secrets (dangerous!)
     Clean { user_id: OwnedUserId },
+    /// Edit a previously sent message
+    Edit {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// The event_id of the message to edit
+        #[arg(short, long, required = true)]
+        event_id: OwnedEventId,
+
+        /// Enable markdown formatting
+        #[arg(short, long)]
+        markdown: bool,
+
+        /// New content of the message; read from stdin if omitted
+        message: Option<String>,
+    },
+    /// Export the full history of a room
+    Export {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "jsonl")]
+        format: ExportFormat,
+
+        /// Output directory
+        #[arg(short, long, required = true)]
+        out: PathBuf,
+
+        /// Continue an interrupted export in the output directory
+        #[arg(long)]
+        resume: bool,
+
+        /// Do not download attached files
+        #[arg(long)]
+        no_media: bool,
+    },
     /// Get information about your homeserver and login
     #[command(alias = "hs")]
     Homeserver {
@@ -72,23 +125,77 @@ enum Command {
     },
     /// Logout and delete all state
     Logout {},
+    /// Manage files on the media repository
+    Media {
+        #[command(subcommand)]
+        command: MediaCommand,
+    },
     /// Dump messages of a room
     Messages {
         #[arg(short, long, required = true)]
-        room_id: OwnedRoomId,
-
-        /// Dump all event types
-        // #[arg(short, long)]
-        // all_types: bool,
+        room_id: RoomRef,
 
-        /// Only request this number of events
+        /// Only request this number of events; 0 means no limit
         #[arg(short, long, default_value = "10")]
         limit: u64,
+
+        /// Pagination token to start from
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Pagination token to stop at
+        #[arg(long)]
+        to: Option<String>,
+
+        /// Paginate forward in time instead of backward
+        #[arg(long)]
+        forward: bool,
+
+        /// Only dump events at or after this timestamp (ms since the unix epoch or RFC 3339)
+        #[arg(long, value_parser = util::parse_timestamp)]
+        since: Option<MilliSecondsSinceUnixEpoch>,
+
+        /// Only dump events at or before this timestamp (ms since the unix epoch or RFC 3339)
+        #[arg(long, value_parser = util::parse_timestamp)]
+        until: Option<MilliSecondsSinceUnixEpoch>,
+
+        /// Only dump events of this type; can be repeated
+        #[arg(short = 't', long = "type")]
+        types: Vec<String>,
+
+        /// Only dump events of this sender; can be repeated
+        #[arg(short, long = "sender")]
+        senders: Vec<OwnedUserId>,
+
+        /// Include the pagination tokens in the output
+        #[arg(long)]
+        tokens: bool,
+    },
+    /// Create polls, vote and show results
+    Poll {
+        #[command(subcommand)]
+        command: PollCommand,
+    },
+    /// React to an event
+    React {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// The event_id to react to
+        #[arg(short, long, required = true)]
+        event_id: OwnedEventId,
+
+        /// Redact the own reaction instead
+        #[arg(long)]
+        remove: bool,
+
+        /// The reaction, e.g. an emoji
+        key: String,
     },
     /// Redact a specific event
     Redact {
         #[arg(short, long, required = true)]
-        room_id: OwnedRoomId,
+        room_id: RoomRef,
 
         #[arg(short, long, required = true)]
         event_id: OwnedEventId,
@@ -96,16 +203,70 @@ enum Command {
         #[arg(long)]
         reason: Option<String>,
     },
+    /// List the reactions to an event
+    Relations {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        #[arg(short, long, required = true)]
+        event_id: OwnedEventId,
+    },
+    /// Create and administer rooms
+    Room {
+        #[command(subcommand)]
+        command: RoomCommand,
+    },
     /// Query room information
     Rooms {
         /// Only query this room
         #[arg(long)]
-        room_id: Option<OwnedRoomId>,
+        room_id: Option<RoomRef>,
+
+        /// How to output avatars
+        #[arg(long, value_enum, default_value = "url")]
+        avatar: AvatarFormat,
+
+        /// Width and height of avatar thumbnails
+        #[arg(long, default_value = "50")]
+        avatar_size: u32,
+
+        /// Resizing method of avatar thumbnails
+        #[arg(long, value_enum, default_value = "scale")]
+        avatar_method: ThumbnailMethod,
+
+        /// Directory for downloaded avatars
+        #[arg(long, default_value = ".")]
+        avatar_dir: PathBuf,
     },
     /// Send a message to a room
+    #[command(group(ArgGroup::new("file").args(["attachment", "voice"]).multiple(true)))]
+    #[command(group(
+        ArgGroup::new("any_location")
+            .args(["location", "live_location"])
+            .multiple(true)
+    ))]
+    #[command(group(
+        ArgGroup::new("target")
+            .args(["room_id", "to", "room_tag", "all_joined"])
+            .required(true)
+            .multiple(true)
+    ))]
     Send {
-        #[arg(short, long, required = true)]
-        room_id: OwnedRoomId,
+        /// Send to this room; can be repeated
+        #[arg(short, long)]
+        room_id: Vec<RoomRef>,
+
+        /// Send to the direct room with this user; it is created if needed
+        #[arg(long)]
+        to: Option<OwnedUserId>,
+
+        /// Send to all joined rooms with this tag, e.g. u.alerts; can be repeated
+        #[arg(long)]
+        room_tag: Vec<String>,
+
+        /// Send to all joined rooms
+        #[arg(long)]
+        all_joined: bool,
 
         /// Enable markdown formatting
         #[arg(short, long)]
@@ -119,21 +280,96 @@ enum Command {
         #[arg(short, long, conflicts_with = "notice")]
         emote: bool,
 
-        /// Send file as an attachment
-        #[arg(short, long, conflicts_with = "message")]
-        attachment: Option<PathBuf>,
+        /// Send file as an attachment; "-" reads stdin; can be repeated; videos are
+        /// sent without thumbnail and only MP4 videos get dimensions and duration
+        #[arg(short, long)]
+        attachment: Vec<PathBuf>,
+
+        /// Send audio file as a voice message; "-" reads stdin
+        #[arg(long, conflicts_with = "attachment")]
+        voice: Option<PathBuf>,
+
+        /// File name of an attachment read from stdin
+        #[arg(long, requires = "file")]
+        filename: Option<String>,
+
+        /// Override the guessed mimetype of the attachments
+        #[arg(long, requires = "file")]
+        mime: Option<::mime::Mime>,
+
+        /// Downscale images so that neither side exceeds this number of pixels
+        #[arg(long, requires = "attachment")]
+        max_dimension: Option<u32>,
+
+        /// Recompress images to this format
+        #[arg(long, value_enum, requires = "attachment")]
+        format: Option<RecompressFormat>,
+
+        /// Quality of recompressed JPEG images; WebP is always lossless
+        #[arg(long, default_value = "80", value_parser = clap::value_parser!(u8).range(1..=100))]
+        quality: u8,
+
+        /// Additionally send the unmodified image as file
+        #[arg(long, requires = "attachment")]
+        keep_original: bool,
 
         /// Reply to a specific event_id
-        #[arg(long, conflicts_with_all = ["notice", "emote", "attachment"])]
+        #[arg(long)]
         reply_to: Option<OwnedEventId>,
 
-        /// String to send; read from stdin if omitted
+        /// Post into the thread of this event_id
+        #[arg(long, conflicts_with = "reply_to")]
+        thread_root: Option<OwnedEventId>,
+
+        /// Send a location as geo: URI, e.g. geo:52.52,13.40;u=35
+        #[arg(long, conflicts_with_all = ["attachment", "voice", "message"])]
+        location: Option<GeoUri>,
+
+        /// Share a live location; geo: URIs are read line by line from stdin
+        #[arg(long, conflicts_with_all = ["attachment", "voice", "message", "location"])]
+        live_location: bool,
+
+        /// Description of the location
+        #[arg(long, requires = "any_location")]
+        description: Option<String>,
+
+        /// Stop sharing the live location after this duration
+        #[arg(long, default_value = "1h", requires = "live_location")]
+        timeout: humantime::Duration,
+
+        /// Mention a user; can be repeated
+        #[arg(long)]
+        mention: Vec<OwnedUserId>,
+
+        /// Mention the whole room
+        #[arg(long)]
+        mention_room: bool,
+
+        /// String to send; read from stdin if omitted; caption with attachments
         message: Option<String>,
     },
+    /// Send an event of any type with JSON content
+    SendEvent {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// The event type, e.g. com.example.alert
+        #[arg(short = 't', long = "type", required = true)]
+        event_type: String,
+
+        /// File with the JSON content; "-" or omitted reads stdin
+        #[arg(long)]
+        content_file: Option<PathBuf>,
+    },
+    /// Manage the state of a room
+    State {
+        #[command(subcommand)]
+        command: StateCommand,
+    },
     /// Run sync and print all events
     Sync {
         #[arg(long)]
-        room_id: Option<OwnedRoomId>,
+        room_id: Option<RoomRef>,
 
         /// Mark all received messages as read
         #[arg(long)]
@@ -142,11 +378,25 @@ enum Command {
         /// Print raw sync events as they come
         #[arg(long)]
         raw: bool,
+
+        /// Join invites from a user (@bot:example.org), a server (example.org)
+        /// or to matching rooms (!*:example.org); can be repeated
+        #[arg(long)]
+        auto_join: Vec<InviteRule>,
+
+        /// Reject invites which are not joined automatically
+        #[arg(long)]
+        reject_invites: bool,
+    },
+    /// List the threads of a room
+    Threads {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
     },
     /// Send typing notifications
     Typing {
         #[arg(long, required = true)]
-        room_id: OwnedRoomId,
+        room_id: RoomRef,
 
         /// Disable typing
         #[arg(long)]
@@ -158,6 +408,230 @@ enum Command {
     Whoami,
 }
 
+#[derive(Debug, Subcommand)]
+enum MediaCommand {
+    /// Download and decrypt a file; metadata is printed as JSON
+    Download {
+        /// The mxc:// URI of the file
+        #[arg(required_unless_present = "event_id", conflicts_with = "event_id")]
+        mxc_uri: Option<OwnedMxcUri>,
+
+        /// Download the file attached to this event
+        #[arg(short, long, requires = "room_id")]
+        event_id: Option<OwnedEventId>,
+
+        #[arg(short, long)]
+        room_id: Option<RoomRef>,
+
+        /// Write the file to this path instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Upload a file and print its mxc:// URI as JSON
+    Upload {
+        /// File to upload; "-" reads from stdin
+        file: PathBuf,
+
+        /// Override the guessed mimetype
+        #[arg(long)]
+        mime: Option<::mime::Mime>,
+
+        /// Encrypt the file before uploading
+        #[arg(short, long)]
+        encrypt: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum PollCommand {
+    /// Start a poll and print its event_id as JSON
+    Create {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        #[arg(long, required = true)]
+        question: String,
+
+        /// A possible answer; repeat for each answer
+        #[arg(short, long, required = true)]
+        answer: Vec<String>,
+
+        /// Show the results while the poll is running (default)
+        #[arg(long, conflicts_with = "undisclosed")]
+        disclosed: bool,
+
+        /// Show the results only after the poll has ended
+        #[arg(long)]
+        undisclosed: bool,
+
+        /// How many answers may be selected per vote
+        #[arg(long, default_value = "1")]
+        max_selections: u32,
+    },
+    /// Vote in a poll; answers are given by id or text
+    Vote {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// The event_id of the poll
+        #[arg(short, long, required = true)]
+        event_id: OwnedEventId,
+
+        #[arg(required = true)]
+        answers: Vec<String>,
+    },
+    /// End a poll and publish its results
+    End {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// The event_id of the poll
+        #[arg(short, long, required = true)]
+        event_id: OwnedEventId,
+    },
+    /// Print the votes per answer as JSON
+    Results {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// The event_id of the poll
+        #[arg(short, long, required = true)]
+        event_id: OwnedEventId,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum RoomCommand {
+    /// Create a room and print its room_id, alias and matrix.to link as JSON
+    Create {
+        #[arg(long)]
+        name: Option<String>,
+
+        #[arg(long)]
+        topic: Option<String>,
+
+        /// Local part of the alias, e.g. "alerts" for #alerts:example.org
+        #[arg(long)]
+        alias: Option<String>,
+
+        /// Invite a user; can be repeated
+        #[arg(long)]
+        invite: Vec<OwnedUserId>,
+
+        #[arg(long, value_enum)]
+        preset: Option<RoomPreset>,
+
+        /// Enable end-to-end encryption
+        #[arg(long)]
+        encrypted: bool,
+
+        /// Mark the room as direct chat with the invited users
+        #[arg(long, requires = "invite")]
+        direct: bool,
+
+        /// Add the room to this space
+        #[arg(long)]
+        space_parent: Option<RoomRef>,
+    },
+    /// Join a room by ID, alias or matrix.to link
+    Join {
+        room: RoomRef,
+
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Leave a room or reject an invite
+    Leave {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Forget a room which has been left
+    Forget {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+    },
+    /// Invite a user to a room
+    Invite {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        user_id: OwnedUserId,
+
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Kick a user from a room
+    Kick {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        user_id: OwnedUserId,
+
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Ban a user from a room
+    Ban {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        user_id: OwnedUserId,
+
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Lift the ban of a user
+    Unban {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        user_id: OwnedUserId,
+
+        #[arg(long)]
+        reason: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum StateCommand {
+    /// Print the current state events as JSON
+    Get {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// Only print events of this type
+        #[arg(short = 't', long = "type")]
+        event_type: Option<String>,
+
+        /// Only print events with this state key
+        #[arg(short = 'k', long)]
+        state_key: Option<String>,
+
+        /// Read from the local store instead of the homeserver; requires --type
+        #[arg(long, requires = "event_type")]
+        cached: bool,
+    },
+    /// Send a state event with JSON content
+    Set {
+        #[arg(short, long, required = true)]
+        room_id: RoomRef,
+
+        /// The event type, e.g. m.room.topic
+        #[arg(short = 't', long = "type", required = true)]
+        event_type: String,
+
+        #[arg(short = 'k', long, default_value = "")]
+        state_key: String,
+
+        /// File with the JSON content; "-" or omitted reads stdin
+        #[arg(long)]
+        content_file: Option<PathBuf>,
+    },
+}
+
 impl Command {
     fn can_sync(&self) -> bool {
         !matches!(
@@ -231,6 +705,18 @@ async fn main() -> anyhow::Result<()> {
         Command::Clean { .. } => {
             client.clean()?;
         }
+        Command::Export {
+            room_id,
+            format,
+            out,
+            resume,
+            no_media,
+        } => {
+            let room_id = client.resolve_room(&room_id).await?;
+            client
+                .export_room(room_id, format, out, resume, !no_media)
+                .await?;
+        }
         Command::Homeserver {
             force,
             include_token,
@@ -299,30 +785,254 @@ async fn main() -> anyhow::Result<()> {
         Command::Logout {} => {
             client.logout().await?;
         }
-        Command::Messages { room_id, limit } => {
-            let msgs = client.messages(room_id, limit).await?;
-            let events: Vec<Box<RawValue>> = msgs
+        Command::Media { command } => match command {
+            MediaCommand::Download {
+                mxc_uri,
+                event_id,
+                room_id,
+                output,
+            } => {
+                let (data, media) = match (mxc_uri, event_id, room_id) {
+                    (Some(mxc_uri), _, _) => client.download_mxc(&mxc_uri).await?,
+                    (None, Some(event_id), Some(room_id)) => {
+                        let room_id = client.resolve_room(&room_id).await?;
+                        client.download_event_media(room_id, &event_id).await?
+                    }
+                    _ => bail!("either mxc uri or event id and room id are required"),
+                };
+
+                // Keep stdout clean for the file if no output path is given.
+                match output {
+                    Some(path) => {
+                        fs::write(path, data)?;
+                        println!("{}", serde_json::to_string(&media)?);
+                    }
+                    None => {
+                        io::stdout().write_all(&data)?;
+                        eprintln!("{}", serde_json::to_string(&media)?);
+                    }
+                }
+            }
+            MediaCommand::Upload {
+                file,
+                mime,
+                encrypt,
+            } => {
+                let data = if file.as_os_str() == "-" {
+                    terminal::read_stdin_to_end()?
+                } else {
+                    fs::read(&file)?
+                };
+                let content_type =
+                    mime.unwrap_or_else(|| crate::mime::guess_mime_data(&file, &data));
+
+                let out = client.upload_media(&content_type, data, encrypt).await?;
+                println!("{}", serde_json::to_string(&out)?);
+            }
+        },
+        Command::Poll { command } => {
+            let (room_id, event_id) = match command {
+                PollCommand::Create {
+                    room_id,
+                    question,
+                    answer,
+                    disclosed: _,
+                    undisclosed,
+                    max_selections,
+                } => {
+                    let room_id = client.resolve_room(&room_id).await?;
+                    let options = PollOptions {
+                        question,
+                        answers: answer,
+                        disclosed: !undisclosed,
+                        max_selections,
+                    };
+                    let event_id = client.create_poll(&room_id, &options).await?;
+                    (room_id, event_id)
+                }
+                PollCommand::Vote {
+                    room_id,
+                    event_id,
+                    answers,
+                } => {
+                    let room_id = client.resolve_room(&room_id).await?;
+                    let event_id = client.vote_poll(&room_id, &event_id, &answers).await?;
+                    (room_id, event_id)
+                }
+                PollCommand::End { room_id, event_id } => {
+                    let room_id = client.resolve_room(&room_id).await?;
+                    let event_id = client.end_poll(&room_id, &event_id).await?;
+                    (room_id, event_id)
+                }
+                PollCommand::Results { room_id, event_id } => {
+                    let room_id = client.resolve_room(&room_id).await?;
+                    let results = client.poll_results(&room_id, &event_id).await?;
+                    println!("{}", serde_json::to_string(&results)?);
+                    return Ok(());
+                }
+            };
+            let sent = outputs::SentEvent { room_id, event_id };
+            println!("{}", serde_json::to_string(&sent)?);
+        }
+        Command::Messages {
+            room_id,
+            limit,
+            from,
+            to,
+            forward,
+            since,
+            until,
+            types,
+            senders,
+            tokens,
+        } => {
+            let room_id = client.resolve_room(&room_id).await?;
+            let query = MessagesQuery {
+                from,
+                to,
+                forward,
+                since,
+                until,
+                types,
+                senders,
+                limit,
+            };
+            let msgs = client.messages(room_id, query).await?;
+            let mut events: Vec<Box<RawValue>> = msgs
                 .chunk
                 .into_iter()
                 .map(|e| e.event.into_json())
-                .rev()
                 .collect();
 
-            println!("{}", serde_json::to_string(&events)?);
+            if !forward {
+                events.reverse();
+            }
+
+            if tokens {
+                let out = outputs::Messages {
+                    start: msgs.start,
+                    end: msgs.end,
+                    chunk: events,
+                };
+                println!("{}", serde_json::to_string(&out)?);
+            } else {
+                println!("{}", serde_json::to_string(&events)?);
+            }
         }
-        Command::Rooms { room_id } => {
+        Command::Room { command } => match command {
+            RoomCommand::Create {
+                name,
+                topic,
+                alias,
+                invite,
+                preset,
+                encrypted,
+                direct,
+                space_parent,
+            } => {
+                let space_parent = match space_parent {
+                    Some(space) => Some(client.resolve_room(&space).await?),
+                    None => None,
+                };
+                let options = CreateRoomOptions {
+                    name,
+                    topic,
+                    alias,
+                    invite,
+                    preset,
+                    encrypted,
+                    direct,
+                    space_parent,
+                };
+                let room = client.create_room(options).await?;
+                println!("{}", serde_json::to_string(&room)?);
+            }
+            RoomCommand::Join { room, reason } => {
+                let membership = client.join_room(&room, reason).await?;
+                println!("{}", serde_json::to_string(&membership)?);
+            }
+            RoomCommand::Leave { room_id, reason } => {
+                let room_id = client.resolve_room(&room_id).await?;
+                let membership = client.leave_room(&room_id, reason).await?;
+                println!("{}", serde_json::to_string(&membership)?);
+            }
+            RoomCommand::Forget { room_id } => {
+                let room_id = client.resolve_room(&room_id).await?;
+                let membership = client.forget_room(&room_id).await?;
+                println!("{}", serde_json::to_string(&membership)?);
+            }
+            RoomCommand::Invite {
+                room_id,
+                user_id,
+                reason,
+            } => {
+                let room_id = client.resolve_room(&room_id).await?;
+                let membership = client.invite_user(&room_id, &user_id, reason).await?;
+                println!("{}", serde_json::to_string(&membership)?);
+            }
+            RoomCommand::Kick {
+                room_id,
+                user_id,
+                reason,
+            } => {
+                let room_id = client.resolve_room(&room_id).await?;
+                let membership = client
+                    .kick_user(&room_id, &user_id, reason.as_deref())
+                    .await?;
+                println!("{}", serde_json::to_string(&membership)?);
+            }
+            RoomCommand::Ban {
+                room_id,
+                user_id,
+                reason,
+            } => {
+                let room_id = client.resolve_room(&room_id).await?;
+                let membership = client
+                    .ban_user(&room_id, &user_id, reason.as_deref())
+                    .await?;
+                println!("{}", serde_json::to_string(&membership)?);
+            }
+            RoomCommand::Unban {
+                room_id,
+                user_id,
+                reason,
+            } => {
+                let room_id = client.resolve_room(&room_id).await?;
+                let membership = client
+                    .unban_user(&room_id, &user_id, reason.as_deref())
+                    .await?;
+                println!("{}", serde_json::to_string(&membership)?);
+            }
+        },
+        Command::Rooms {
+            room_id,
+            avatar,
+            avatar_size,
+            avatar_method,
+            avatar_dir,
+        } => {
+            let avatar_options = AvatarOptions {
+                format: avatar,
+                thumbnail: Thumbnail {
+                    width: avatar_size,
+                    height: avatar_size,
+                    method: avatar_method,
+                },
+                dir: avatar_dir,
+            };
             let out = match room_id {
                 Some(room_id) => {
+                    let room_id = client.resolve_room(&room_id).await?;
                     let Some(room) = client.get_room(&room_id) else {
                         bail!("no such room: {}", room_id);
                     };
-                    let output = client.query_room(room).await?;
+                    let output = client.query_room(room, &avatar_options).await?;
                     serde_json::to_string(&output)?
                 }
                 None => {
                     let mut output = vec![];
                     for room in client.rooms() {
-                        output.push(client.query_room(room).await?);
+                        output.push(client.query_room(room, &avatar_options).await?);
                     }
                     serde_json::to_string(&output)?
                 }
@@ -335,6 +1045,7 @@ async fn main() -> anyhow::Result<()> {
             event_id,
             reason,
         } => {
+            let room_id = client.resolve_room(&room_id).await?;
             let room = client.get_joined_room(room_id)?;
             room.redact(&event_id, reason.as_ref().map(String::as_ref), None)
                 .await?;
@@ -345,41 +1056,260 @@ async fn main() -> anyhow::Result<()> {
         }
         Command::Send {
             room_id,
+            to,
+            room_tag,
+            all_joined,
             reply_to,
+            thread_root,
+            location,
+            live_location,
+            description,
+            timeout,
+            mention,
+            mention_room,
             markdown,
             notice,
             emote,
             attachment,
+            voice,
+            filename,
+            mime,
+            max_dimension,
+            format,
+            quality,
+            keep_original,
             message,
         } => {
-            if let Some(path) = attachment {
-                return client.send_attachment(room_id, path).await;
+            let mut room_ids = client
+                .broadcast_rooms(&room_id, &room_tag, all_joined)
+                .await?;
+            if let Some(user_id) = to {
+                let room_id = client.direct_room(&user_id).await?;
+                if !room_ids.contains(&room_id) {
+                    room_ids.push(room_id);
+                }
+            }
+            if room_ids.is_empty() {
+                bail!("no rooms to send to");
+            }
+            // The related event only exists in a single room.
+            if (reply_to.is_some() || thread_root.is_some()) && room_ids.len() > 1 {
+                bail!("--reply-to and --thread-root require a single room");
             }
 
-            let body = match message {
-                Some(message) => message,
-                None => terminal::read_stdin_to_string()?,
+            let relation = match (&reply_to, &thread_root) {
+                (Some(event_id), _) => Some(MessageRelation::Reply(event_id)),
+                (None, Some(event_id)) => Some(MessageRelation::Thread(event_id)),
+                (None, None) => None,
+            };
+            let mentions = MentionOptions {
+                users: mention,
+                room: mention_room,
             };
 
-            if let Some(ref event_id) = reply_to {
+            if live_location {
+                let [room_id] = &room_ids[..] else {
+                    bail!("a live location can only be shared with a single room");
+                };
                 return client
-                    .send_message_reply(room_id, event_id, &body, markdown)
+                    .share_live_location(room_id, description.as_deref(), timeout.into())
                     .await;
             }
 
-            if notice {
-                client.send_notice(room_id, &body, markdown).await?;
-            } else if emote {
-                client.send_emote(room_id, &body, markdown).await?;
+            let mut payload = if let Some(geo_uri) = location {
+                Payload::Location {
+                    geo_uri,
+                    description,
+                }
+            } else if let Some(path) = voice {
+                let options = AttachmentOptions {
+                    mime,
+                    image: ImageOptions {
+                        max_dimension: None,
+                        format: None,
+                        quality,
+                    },
+                    keep_original: false,
+                    voice: true,
+                };
+                let attachment =
+                    Attachment::load(path, filename.as_deref(), options.mime.as_ref())?;
+                Payload::Attachments {
+                    attachments: vec![PreparedAttachment::new(attachment, &options)?],
+                    caption: message.map(|body| Caption { body, markdown }),
+                }
+            } else if !attachment.is_empty() {
+                if attachment.iter().filter(|p| p.as_os_str() == "-").count() > 1 {
+                    bail!("stdin can only be used for one attachment");
+                }
+
+                let options = AttachmentOptions {
+                    mime,
+                    image: ImageOptions {
+                        max_dimension,
+                        format,
+                        quality,
+                    },
+                    keep_original,
+                    voice: false,
+                };
+                let mut attachments = Vec::new();
+                for path in attachment {
+                    let attachment =
+                        Attachment::load(path, filename.as_deref(), options.mime.as_ref())?;
+                    attachments.push(PreparedAttachment::new(attachment, &options)?);
+                }
+                Payload::Attachments {
+                    attachments,
+                    caption: message.map(|body| Caption { body, markdown }),
+                }
             } else {
-                client.send_message(room_id, &body, markdown).await?;
+                let body = match message {
+                    Some(message) => message,
+                    None => terminal::read_stdin_to_string()?,
+                };
+                Payload::Text {
+                    body,
+                    markdown,
+                    notice,
+                    emote,
+                }
+            };
+
+            // Keep going on errors, so that one broken room does not
+            // prevent the others from being notified.
+            let mut failed = 0;
+            for room_id in &room_ids {
+                match client
+                    .send_payload(room_id, &mut payload, relation, &mentions)
+                    .await
+                {
+                    Ok(event_ids) => {
+                        for event_id in event_ids {
+                            let sent = outputs::SentEvent {
+                                room_id: room_id.clone(),
+                                event_id,
+                            };
+                            println!("{}", serde_json::to_string(&sent)?);
+                        }
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        let failure = outputs::SendFailure {
+                            room_id: room_id.clone(),
+                            error: e.to_string(),
+                        };
+                        println!("{}", serde_json::to_string(&failure)?);
+                    }
+                }
             }
+            if failed > 0 {
+                bail!("sending failed for {} of {} rooms", failed, room_ids.len());
+            }
+        }
+        Command::Edit {
+            room_id,
+            event_id,
+            markdown,
+            message,
+        } => {
+            let room_id = client.resolve_room(&room_id).await?;
+            let body = match message {
+                Some(message) => message,
+                None => terminal::read_stdin_to_string()?,
+            };
+            let event_id = client
+                .edit_message(&room_id, &event_id, &body, markdown)
+                .await?;
+            let sent = outputs::SentEvent { room_id, event_id };
+            println!("{}", serde_json::to_string(&sent)?);
+        }
+        Command::React {
+            room_id,
+            event_id,
+            remove,
+            key,
+        } => {
+            let room_id = client.resolve_room(&room_id).await?;
+            let event_id = if remove {
+                client.remove_reaction(&room_id, &event_id, &key).await?
+            } else {
+                client.send_reaction(&room_id, &event_id, &key).await?
+            };
+            let sent = outputs::SentEvent { room_id, event_id };
+            println!("{}", serde_json::to_string(&sent)?);
+        }
+        Command::Relations { room_id, event_id } => {
+            let room_id = client.resolve_room(&room_id).await?;
+            let reactions = client.reactions(&room_id, &event_id).await?;
+            println!("{}", serde_json::to_string(&reactions)?);
+        }
+        Command::Threads { room_id } => {
+            let room_id = client.resolve_room(&room_id).await?;
+            let threads = client.threads(&room_id).await?;
+            println!("{}", serde_json::to_string(&threads)?);
         }
+        Command::SendEvent {
+            room_id,
+            event_type,
+            content_file,
+        } => {
+            let room_id = client.resolve_room(&room_id).await?;
+            let content = util::read_event_content(content_file.as_deref())?;
+            let event_id = client
+                .send_custom_event(&room_id, &event_type, content)
+                .await?;
+            let sent = outputs::SentEvent { room_id, event_id };
+            println!("{}", serde_json::to_string(&sent)?);
+        }
+        Command::State { command } => match command {
+            StateCommand::Get {
+                room_id,
+                event_type,
+                state_key,
+                cached,
+            } => {
+                let room_id = client.resolve_room(&room_id).await?;
+                let events = client
+                    .room_state(
+                        &room_id,
+                        event_type.as_deref(),
+                        state_key.as_deref(),
+                        cached,
+                    )
+                    .await?;
+                println!("{}", serde_json::to_string(&events)?);
+            }
+            StateCommand::Set {
+                room_id,
+                event_type,
+                state_key,
+                content_file,
+            } => {
+                let room_id = client.resolve_room(&room_id).await?;
+                let content = util::read_event_content(content_file.as_deref())?;
+                let event_id = client
+                    .send_state_event(&room_id, &event_type, &state_key, content)
+                    .await?;
+                let sent = outputs::SentEvent { room_id, event_id };
+                println!("{}", serde_json::to_string(&sent)?);
+            }
+        },
         Command::Sync {
             room_id,
             receipt,
             raw,
+            auto_join,
+            reject_invites,
         } => {
+            let policy = InvitePolicy {
+                rules: auto_join,
+                reject: reject_invites,
+            };
+            if !policy.is_empty() {
+                client.handle_invites(policy);
+            }
+
             if raw {
                 let mut sync_stream = Box::pin(client.sync_stream(sync_settings.clone()).await);
                 while let Some(Ok(response)) = sync_stream.next().await {
@@ -387,8 +1317,9 @@ async fn main() -> anyhow::Result<()> {
                     println!("{}", serde_json::to_string(&resp)?);
                 }
             } else {
-                if let Some(ref room_id) = room_id {
-                    client.add_room_event_handler(room_id, move |event, room| async move {
+                if let Some(room_id) = room_id {
+                    let room_id = client.resolve_room(&room_id).await?;
+                    client.add_room_event_handler(&room_id, move |event, room| async move {
                         on_room_message(event, room, receipt).await
                     });
                 } else {
@@ -401,6 +1332,7 @@ async fn main() -> anyhow::Result<()> {
             }
         }
         Command::Typing { room_id, disable } => {
+            let room_id = client.resolve_room(&room_id).await?;
             let room = client.get_joined_room(room_id)?;
             room.typing_notice(!disable).await?;
         }
@@ -1,58 +1,84 @@
 use std::path::Path;
-use std::process::{Command, Stdio};
 
-use anyhow::{anyhow, bail};
-use tracing::warn;
+pub fn guess_mime_bytes(data: &[u8]) -> Option<mime::Mime> {
+    if let Some(kind) = infer::get(data) {
+        return kind.mime_type().parse().ok();
+    }
 
-pub fn guess_mime_file(path: impl AsRef<Path>) -> anyhow::Result<mime::Mime> {
-    let child = Command::new("file")
-        .arg("--mime")
-        .arg(path.as_ref().as_os_str())
-        .stdout(Stdio::piped())
-        .spawn()?;
+    // The buffer might end in the middle of a multibyte character.
+    let is_utf8 = match std::str::from_utf8(data) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if !data.is_empty() && !data.contains(&0) && is_utf8 {
+        return Some(mime::TEXT_PLAIN_UTF_8);
+    }
 
-    let output = child.wait_with_output()?;
+    None
+}
 
-    if !output.status.success() {
-        bail!("the file tool failed with exit code: {}", output.status);
-    }
+pub fn guess_mime_extension(path: impl AsRef<Path>) -> Option<mime::Mime> {
+    let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
 
-    let raw_output = String::from_utf8(output.stdout)?;
-    let raw_mime = raw_output
-        .split_once(':')
-        .map(|x| x.1)
-        .ok_or(anyhow!("bug? no mime in output"))?
-        .trim();
+    // Prefer the types which matrix clients are able to preview.
+    match extension.as_str() {
+        "opus" | "ogg" | "oga" => Some("audio/ogg".parse().unwrap()),
+        "mp3" => Some("audio/mpeg".parse().unwrap()),
+        ext => mime_guess::from_ext(ext).first(),
+    }
+}
 
-    Ok(raw_mime.parse()?)
+pub(crate) fn guess_mime_data(path: impl AsRef<Path>, data: &[u8]) -> mime::Mime {
+    // Plain text could be anything, e.g. CSV or source code.
+    // In this case the extension is more precise.
+    match guess_mime_bytes(data) {
+        Some(mime) if mime != mime::TEXT_PLAIN_UTF_8 => mime,
+        sniffed => guess_mime_extension(&path)
+            .or(sniffed)
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM),
+    }
 }
 
-pub fn guess_mime_extension(path: impl AsRef<Path>) -> anyhow::Result<mime::Mime> {
-    let extension = path.as_ref().extension();
-
-    let res = match extension {
-        None => mime::APPLICATION_OCTET_STREAM,
-        Some(s) => match s.to_str().unwrap().to_lowercase().as_str() {
-            "jpg" | "jpeg" => mime::IMAGE_JPEG,
-            "gif" => mime::IMAGE_GIF,
-            "png" => mime::IMAGE_PNG,
-            "pdf" => mime::APPLICATION_PDF,
-            "opus" | "ogg" => "audio/ogg".parse().unwrap(),
-            "mp3" => "audio/mp3".parse().unwrap(),
-            _ => mime::APPLICATION_OCTET_STREAM,
-        },
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(res)
-}
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0";
+
+    #[test]
+    fn sniffed_type_wins_over_extension() {
+        assert_eq!(guess_mime_data("image.jpg", PNG), mime::IMAGE_PNG);
+        assert_eq!(guess_mime_data("report.txt", PNG), mime::IMAGE_PNG);
+        assert_eq!(guess_mime_data("image", PNG), mime::IMAGE_PNG);
+    }
+
+    #[test]
+    fn text_uses_extension() {
+        let csv = b"name,size\ncat.jpg,1234\n";
+        assert_eq!(guess_mime_bytes(csv), Some(mime::TEXT_PLAIN_UTF_8));
+        assert_eq!(guess_mime_data("data.csv", csv), mime::TEXT_CSV);
+
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#;
+        assert_eq!(guess_mime_data("icon.svg", svg), mime::IMAGE_SVG);
+
+        assert_eq!(guess_mime_data("notes", csv), mime::TEXT_PLAIN_UTF_8);
+    }
+
+    #[test]
+    fn empty_data() {
+        assert_eq!(guess_mime_bytes(b""), None);
+        assert_eq!(guess_mime_data("data", b""), mime::APPLICATION_OCTET_STREAM);
+        assert_eq!(guess_mime_data("data.csv", b""), mime::TEXT_CSV);
+    }
+
+    #[test]
+    fn truncated_utf8() {
+        // "grüß" cut in the middle of "ß".
+        let data = &"grüß".as_bytes()[..5];
+        assert!(std::str::from_utf8(data).is_err());
+        assert_eq!(guess_mime_bytes(data), Some(mime::TEXT_PLAIN_UTF_8));
 
-pub(crate) fn guess_mime(path: impl AsRef<Path>) -> anyhow::Result<mime::Mime> {
-    match guess_mime_file(&path) {
-        Ok(mime) => Ok(mime),
-        Err(e) => {
-            warn!("getting mimetype with `file` tool failed: {:?}", e);
-            warn!("choosing mimetype from file extension");
-            guess_mime_extension(&path)
-        }
+        assert_eq!(guess_mime_bytes(b"gr\xfc\xdf!"), None);
+        assert_eq!(guess_mime_bytes(b"text\0"), None);
     }
 }
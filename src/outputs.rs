@@ -9,12 +9,15 @@ use matrix_sdk::{
     deserialized_responses::SyncTimelineEvent,
     ruma::{
         api::client::push::get_notifications::v3::Notification,
+        events::room::member::MembershipState,
+        events::room::EncryptedFile,
         events::{presence::PresenceEvent, AnyGlobalAccountDataEvent, AnyToDeviceEvent},
         serde::Raw,
-        OwnedRoomId,
+        MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId,
+        OwnedUserId, UInt,
     },
 };
-// use serde_json::value::RawValue;
+use serde_json::value::RawValue;
 
 #[derive(Serialize)]
 pub(crate) struct SSRoom {
@@ -49,6 +52,108 @@ pub(crate) struct Room {
     // pub(crate) events: Vec<Box<RawValue>>,
 }
 
+#[derive(Serialize)]
+pub(crate) struct CreatedRoom {
+    pub(crate) room_id: OwnedRoomId,
+    pub(crate) alias: Option<OwnedRoomAliasId>,
+    pub(crate) matrix_to_uri: String,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum InviteAction {
+    Join,
+    Reject,
+    Ignore,
+}
+
+#[derive(Serialize)]
+pub(crate) struct InviteDecision {
+    pub(crate) room_id: OwnedRoomId,
+    pub(crate) inviter: OwnedUserId,
+    pub(crate) action: InviteAction,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Membership {
+    pub(crate) room_id: OwnedRoomId,
+    pub(crate) user_id: OwnedUserId,
+    pub(crate) membership: MembershipState,
+}
+
+#[derive(Serialize)]
+pub(crate) struct SentEvent {
+    pub(crate) room_id: OwnedRoomId,
+    pub(crate) event_id: OwnedEventId,
+}
+
+#[derive(Serialize)]
+pub(crate) struct SendFailure {
+    pub(crate) room_id: OwnedRoomId,
+    pub(crate) error: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Reaction {
+    pub(crate) key: String,
+    pub(crate) count: usize,
+    pub(crate) senders: Vec<OwnedUserId>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct PollAnswer {
+    pub(crate) id: String,
+    pub(crate) text: String,
+    pub(crate) count: usize,
+    pub(crate) voters: Vec<OwnedUserId>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct PollResults {
+    pub(crate) event_id: OwnedEventId,
+    pub(crate) question: String,
+    pub(crate) kind: String,
+    pub(crate) max_selections: UInt,
+    pub(crate) ended: bool,
+    pub(crate) answers: Vec<PollAnswer>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Thread {
+    pub(crate) event_id: OwnedEventId,
+    pub(crate) sender: String,
+    pub(crate) origin_server_ts: MilliSecondsSinceUnixEpoch,
+    pub(crate) body: Option<String>,
+    pub(crate) count: UInt,
+    pub(crate) latest_event_id: Option<OwnedEventId>,
+    pub(crate) latest_activity: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Messages {
+    pub(crate) start: Option<String>,
+    pub(crate) end: Option<String>,
+    pub(crate) chunk: Vec<Box<RawValue>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Media {
+    pub(crate) mxc_uri: String,
+    pub(crate) filename: Option<String>,
+    pub(crate) mimetype: Option<String>,
+    pub(crate) size: Option<u64>,
+    pub(crate) encrypted: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Upload {
+    pub(crate) mxc_uri: OwnedMxcUri,
+    pub(crate) mimetype: String,
+    pub(crate) size: u64,
+    pub(crate) encryption: Option<EncryptedFile>,
+}
+
 #[derive(Serialize)]
 pub(crate) struct RoomMember {
     pub(crate) name: String,
@@ -24,6 +24,12 @@ pub(crate) fn read_stdin_to_string() -> io::Result<String> {
     Ok(buf)
 }
 
+pub(crate) fn read_stdin_to_end() -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    io::stdin().read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
 pub(crate) async fn confirm(question: &str) -> anyhow::Result<bool> {
     let prompt = Confirmation::new(question)
         .default_positive(false)
@@ -1,3 +1,11 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+use matrix_sdk::ruma::{MilliSecondsSinceUnixEpoch, UInt};
+
+use crate::terminal;
+
 pub fn convert_filter(filter: log::LevelFilter) -> tracing_subscriber::filter::LevelFilter {
     match filter {
         log::LevelFilter::Off => tracing_subscriber::filter::LevelFilter::OFF,
@@ -8,3 +16,54 @@ pub fn convert_filter(filter: log::LevelFilter) -> tracing_subscriber::filter::L
         log::LevelFilter::Trace => tracing_subscriber::filter::LevelFilter::TRACE,
     }
 }
+
+pub(crate) fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Parses a timestamp given as milliseconds since the unix epoch or as
+/// RFC 3339 date, e.g. `2024-05-01T12:00:00Z`.
+pub(crate) fn parse_timestamp(s: &str) -> anyhow::Result<MilliSecondsSinceUnixEpoch> {
+    if let Ok(ms) = s.parse::<UInt>() {
+        return Ok(MilliSecondsSinceUnixEpoch(ms));
+    }
+    let time = humantime::parse_rfc3339_weak(s)
+        .map_err(|e| anyhow!("expected milliseconds or an RFC 3339 timestamp: {}", e))?;
+    MilliSecondsSinceUnixEpoch::from_system_time(time)
+        .ok_or_else(|| anyhow!("timestamp out of range: {}", s))
+}
+
+/// Replaces all characters which might be problematic in file names.
+pub(crate) fn sanitize_filename(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Reads the content of an event as JSON object from a file or, if
+/// `path` is `None` or "-", from stdin.
+pub(crate) fn read_event_content(path: Option<&Path>) -> anyhow::Result<serde_json::Value> {
+    let raw = match path {
+        Some(path) if path.as_os_str() != "-" => fs::read_to_string(path)?,
+        _ => terminal::read_stdin_to_string()?,
+    };
+    let content: serde_json::Value = serde_json::from_str(&raw)?;
+    if !content.is_object() {
+        bail!("event content must be a JSON object");
+    }
+    Ok(content)
+}